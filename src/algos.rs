@@ -1,23 +1,376 @@
-use std::error::Error;
-use std::io::{Cursor, Read, Write};
-
-use lz4::{Decoder, Encoder, EncoderBuilder};
+use lz4::block::{self, CompressionMode};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::mem;
 
 use crate::constants::*;
+use crate::error::{Error, Result};
+use crate::frame;
+
+mod auto;
+#[cfg(feature = "brotli")]
+mod brotli;
+#[cfg(feature = "deflate")]
+mod deflate;
+mod hashref;
+mod huffman;
+mod lz4f;
+mod pack5;
+mod parallel;
+mod pool;
+#[cfg(feature = "snappy")]
+mod snappy;
+mod sparse;
+mod tsdelta;
+pub mod varint;
+
+pub use self::auto::Auto;
+#[cfg(feature = "brotli")]
+pub use self::brotli::Brotli;
+#[cfg(feature = "deflate")]
+pub use self::deflate::Deflate;
+pub use self::hashref::HashRef;
+pub use self::huffman::{Huffman, TryteModel};
+pub use self::pack5::Pack5;
+pub use self::parallel::{compress_batch, decompress_batch};
+use self::pool::Pool;
+#[cfg(feature = "snappy")]
+pub use self::snappy::Snappy;
+pub use self::sparse::Sparse;
+pub use self::tsdelta::TimestampDelta;
+
+/// Codecs are shared between threads, e.g. by `compress_batch`, so they must be `Send + Sync`.
+///
+/// Codecs write into buffers of the caller and keep whatever else they need, like contexts of
+/// the underlying libraries, for the next call. A caller that reuses its buffers doesn't allocate
+/// once they have grown large enough. `Brotli` is the exception, it allocates its state per call.
+pub trait CompressionAlgo: Send + Sync {
+    /// Appends the compressed `bytes` to `buf` and returns their length. On error the bytes
+    /// appended to `buf` are unspecified.
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize>;
+
+    /// Appends the decompressed `bytes` to `buf` and returns their length. On error the bytes
+    /// appended to `buf` are unspecified.
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize>;
+
+    /// The codecs applied by `compress` in that order. Written into the header of every datagram.
+    fn codec_ids(&self) -> Vec<CodecId>;
+
+    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut compressed = Vec::new();
+        self.compress_into(bytes, &mut compressed)?;
+        Ok(compressed)
+    }
+
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        self.decompress_into(bytes, &mut decompressed)?;
+        Ok(decompressed)
+    }
+
+    /// Whether the codec expects the bytes of a single transaction. In a batch such codecs are
+    /// applied to each transaction, all others to the whole batch.
+    fn per_transaction(&self) -> bool {
+        false
+    }
+
+    /// Applies the part of the codec that works on single transactions of a batch and appends
+    /// the output to `buf`.
+    fn compress_each_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if self.per_transaction() {
+            self.compress_into(bytes, buf)
+        } else {
+            buf.extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+    }
+
+    /// Applies the part of the codec that works on the records of a whole batch and appends the
+    /// output to `buf`.
+    fn compress_joint_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if self.per_transaction() {
+            buf.extend_from_slice(bytes);
+            Ok(bytes.len())
+        } else {
+            self.compress_into(bytes, buf)
+        }
+    }
+
+    /// Whether the output depends on the inputs that came before, like with `HashRef`. Such codecs
+    /// must see their inputs one at a time and in order.
+    fn stateful(&self) -> bool {
+        false
+    }
+
+    /// Statistics the sender prints after each datagram, if the codec keeps any.
+    fn report(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Identifies a codec on the wire. Values must never be reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CodecId {
+    Lz4 = 1,
+    Zstd = 2,
+    TrimFragment = 3,
+    TrimAll = 4,
+    Sparse = 5,
+    Pack5 = 6,
+    HashRef = 7,
+    Lz4Block = 8,
+    Huffman = 9,
+    TimestampDelta = 10,
+    Auto = 11,
+    Snappy = 12,
+    Deflate = 13,
+    Brotli = 14,
+}
+
+impl CodecId {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(CodecId::Lz4),
+            2 => Some(CodecId::Zstd),
+            3 => Some(CodecId::TrimFragment),
+            4 => Some(CodecId::TrimAll),
+            5 => Some(CodecId::Sparse),
+            6 => Some(CodecId::Pack5),
+            7 => Some(CodecId::HashRef),
+            8 => Some(CodecId::Lz4Block),
+            9 => Some(CodecId::Huffman),
+            10 => Some(CodecId::TimestampDelta),
+            11 => Some(CodecId::Auto),
+            12 => Some(CodecId::Snappy),
+            13 => Some(CodecId::Deflate),
+            14 => Some(CodecId::Brotli),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CodecId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            CodecId::Lz4 => "lz4",
+            CodecId::Zstd => "zstd",
+            CodecId::TrimFragment => "trimfrag",
+            CodecId::TrimAll => "trimall",
+            CodecId::Sparse => "sparse",
+            CodecId::Pack5 => "pack5",
+            CodecId::HashRef => "hashref",
+            CodecId::Lz4Block => "lz4block",
+            CodecId::Huffman => "huffman",
+            CodecId::TimestampDelta => "tsdelta",
+            CodecId::Auto => "auto",
+            CodecId::Snappy => "snappy",
+            CodecId::Deflate => "deflate",
+            CodecId::Brotli => "brotli",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Knows how to decompress every codec, so the receiver can decode whatever the sender chose.
+pub struct Registry {
+    algos: HashMap<CodecId, Box<dyn CompressionAlgo>>,
+    scratch: Pool<(Vec<u8>, Vec<u8>)>,
+    auto_ids: Pool<Vec<CodecId>>,
+}
+
+impl Registry {
+    /// The dictionary is used for Zstd frames and may be empty. The model must match the one of
+    /// the sender's Huffman codec.
+    pub fn new(dictionary: Vec<u8>, model: &TryteModel) -> Self {
+        let mut algos: HashMap<CodecId, Box<dyn CompressionAlgo>> = HashMap::new();
+        algos.insert(CodecId::Lz4, Box::new(Lz4::new(0)));
+        algos.insert(
+            CodecId::Zstd,
+            Box::new(Zstd::with_dictionary(0, dictionary)),
+        );
+        algos.insert(CodecId::TrimFragment, Box::new(TrimFragment));
+        algos.insert(CodecId::TrimAll, Box::new(TrimAll::new()));
+        algos.insert(CodecId::Sparse, Box::new(Sparse));
+        algos.insert(CodecId::Pack5, Box::new(Pack5));
+        algos.insert(CodecId::HashRef, Box::new(HashRef::new()));
+        algos.insert(CodecId::Lz4Block, Box::new(Lz4Block::new(0, 1)));
+        algos.insert(CodecId::Huffman, Box::new(Huffman::new(model)));
+        algos.insert(CodecId::TimestampDelta, Box::new(TimestampDelta));
+        #[cfg(feature = "snappy")]
+        algos.insert(CodecId::Snappy, Box::new(Snappy::new()));
+        #[cfg(feature = "deflate")]
+        algos.insert(CodecId::Deflate, Box::new(Deflate::new(6)));
+        #[cfg(feature = "brotli")]
+        algos.insert(CodecId::Brotli, Box::new(Brotli::new(11, 22)));
+
+        Registry {
+            algos,
+            scratch: Pool::default(),
+            auto_ids: Pool::default(),
+        }
+    }
+
+    /// Undoes the given codecs in reverse order.
+    pub fn decompress(&self, codec_ids: &[CodecId], bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        self.decompress_into(codec_ids, bytes, &mut decompressed)?;
+        Ok(decompressed)
+    }
+
+    /// Like `decompress`, but appends the output to `buf` and returns its length. The steps in
+    /// between reuse scratch buffers of earlier calls, one pair per concurrent caller.
+    pub fn decompress_into(
+        &self,
+        codec_ids: &[CodecId],
+        bytes: &[u8],
+        buf: &mut Vec<u8>,
+    ) -> Result<usize> {
+        let (&last, others) = match codec_ids.split_first() {
+            Some(split) => split,
+            None => {
+                buf.extend_from_slice(bytes);
+                return Ok(bytes.len());
+            }
+        };
+
+        self.scratch.with(
+            || Ok(Default::default()),
+            |(input, output)| {
+                input.clear();
+                input.extend_from_slice(bytes);
+
+                for &id in others.iter().rev() {
+                    output.clear();
+                    self.decompress_one_into(id, input, output)?;
+                    mem::swap(input, output);
+                }
+
+                self.decompress_one_into(last, input, buf)
+            },
+        )
+    }
+
+    fn decompress_one_into(&self, id: CodecId, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        match id {
+            CodecId::Auto => self.decompress_auto_into(bytes, buf),
+            _ => self.get(id)?.decompress_into(bytes, buf),
+        }
+    }
+
+    /// `Auto` output names the codecs of the winner, which may be any registered codec.
+    fn decompress_auto_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        self.auto_ids.with(
+            || Ok(Vec::new()),
+            |codec_ids| {
+                let payload = frame::read_codec_ids_into(bytes, codec_ids)?;
+                if codec_ids.contains(&CodecId::Auto) {
+                    return Err(Error::UnknownCodec(CodecId::Auto as u8));
+                }
+
+                self.decompress_into(codec_ids, payload, buf)
+            },
+        )
+    }
+
+    /// Whether any of the given codecs is stateful, so that it must see every datagram once and
+    /// in order.
+    pub fn stateful(&self, codec_ids: &[CodecId]) -> bool {
+        codec_ids
+            .iter()
+            .any(|&id| self.get(id).is_ok_and(|algo| algo.stateful()))
+    }
+
+    fn get(&self, id: CodecId) -> Result<&dyn CompressionAlgo> {
+        self.algos
+            .get(&id)
+            .map(|algo| algo.as_ref())
+            .ok_or(Error::UnknownCodec(id as u8))
+    }
+
+    /// Undoes the given codecs on a batch and appends the bytes of each transaction to `buf`,
+    /// pushing where each of them ends onto `ends`. The leading per-transaction codecs are undone
+    /// on each record, all others on the whole batch.
+    pub fn decompress_many_into(
+        &self,
+        codec_ids: &[CodecId],
+        bytes: &[u8],
+        buf: &mut Vec<u8>,
+        ends: &mut Vec<usize>,
+    ) -> Result<()> {
+        let mut num_per_transaction = 0;
+        for &id in codec_ids {
+            if id != CodecId::Auto && !self.get(id)?.per_transaction() {
+                break;
+            }
+            num_per_transaction += 1;
+        }
+        let (each_ids, joint_ids) = codec_ids.split_at(num_per_transaction);
+
+        self.scratch.with(
+            || Ok(Default::default()),
+            |(records, _)| {
+                records.clear();
+                self.decompress_into(joint_ids, bytes, records)?;
+
+                for record in frame::records(records)? {
+                    self.decompress_into(each_ids, record?, buf)?;
+                    ends.push(buf.len());
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+/// Upper bound for the output of codecs that don't announce it up front, so a malformed packet
+/// can't make us allocate gigabytes.
+pub const MAX_DECOMPRESSED_SIZE: u64 = 1 << 20;
 
-pub trait CompressionAlgo {
-    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<Error>>;
-    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<Error>>;
+/// Appends the decompressed bytes from a decoder to `buf` and fails if there are more than
+/// `MAX_DECOMPRESSED_SIZE`.
+fn read_limited(decoder: impl Read, buf: &mut Vec<u8>) -> Result<usize> {
+    let size = decoder.take(MAX_DECOMPRESSED_SIZE + 1).read_to_end(buf)?;
+
+    if size as u64 > MAX_DECOMPRESSED_SIZE {
+        return Err(Error::OutputOverflow);
+    }
+
+    Ok(size)
 }
 
-/// LZ4 compression algo.
+/// LZ4 compression algo. Creates frame contexts as needed and reuses them for later packets, one
+/// per thread compressing or decompressing at the same time.
 pub struct Lz4 {
     enc_level: u32,
+    encoders: Pool<lz4f::FrameEncoder>,
+    decoders: Pool<lz4f::FrameDecoder>,
+}
+
+/// LZ4 compression algo without the frame format. Only prepends the uncompressed length.
+pub struct Lz4Block {
+    enc_level: u32,
+    acceleration: i32,
+}
+
+/// Zstandard compression algo. Can optionally be primed with a dictionary trained from
+/// transaction bytes. Reuses its contexts like `Lz4`.
+pub struct Zstd {
+    enc_level: i32,
+    dictionary: Vec<u8>,
+    compressors: Pool<zstd::bulk::Compressor<'static>>,
+    decompressors: Pool<zstd::bulk::Decompressor<'static>>,
 }
 
 /// Trims the signature message fragment only.
 pub struct TrimFragment;
 
+/// Chains several compression algos. Compresses in the given order and decompresses in reverse.
+pub struct Pipeline {
+    stages: Vec<Box<dyn CompressionAlgo>>,
+    scratch: Pool<(Vec<u8>, Vec<u8>)>,
+}
+
 /// Trims all the transactions fields. It simply puts delimiter bytes between all fields.
 pub struct TrimAll {
     offsets: Vec<(usize, usize)>,
@@ -56,108 +409,330 @@ impl TrimAll {
 }
 
 impl CompressionAlgo for TrimAll {
-    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<Error>> {
-        let mut kept_bytes_list = Vec::with_capacity(self.offsets.len());
-        let mut kept_bytes = 0;
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() != PACKET_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+
+        let start = buf.len();
+
+        for (last, length) in &self.offsets {
+            let field = &bytes[last + 1 - length..=*last];
+            let kept = field.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+
+            buf.extend_from_slice(&field[..kept]);
+            buf.push(TrimAll::DELIMITER_BYTE);
+        }
+
+        Ok(buf.len() - start)
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let start = buf.len();
+        buf.resize(start + PACKET_SIZE, 0);
+        let decompressed = &mut buf[start..];
+
+        let mut src = 0;
 
         for (last, length) in &self.offsets {
             let (last, length) = (*last, *length);
 
-            let mut is_empty_field = true;
+            let delta = bytes[src..]
+                .iter()
+                .position(|&b| b == TrimAll::DELIMITER_BYTE)
+                .ok_or(Error::TruncatedInput)?;
 
-            for i in 0..length {
-                if bytes[last - i] != 0 {
-                    kept_bytes_list.push((last + 1 - length, length - i));
-                    kept_bytes += length - i + 1; // +1 for the delimiter byte
-                    is_empty_field = false;
-                    break;
-                }
+            if delta > length {
+                return Err(Error::OutputOverflow);
             }
 
-            if is_empty_field {
-                kept_bytes_list.push((last + 1 - length, 0));
-                kept_bytes += 1;
-            }
+            let dst = last + 1 - length;
+            decompressed[dst..dst + delta].copy_from_slice(&bytes[src..src + delta]);
+
+            src += delta + 1;
         }
 
-        let mut compressed = vec![0; kept_bytes];
+        // There must be nothing behind the delimiter of the last field
+        if src != bytes.len() {
+            return Err(Error::BadDelimiter);
+        }
 
-        let mut start = 0;
-        for (offset, length) in kept_bytes_list {
-            compressed[start..start + length].copy_from_slice(&bytes[offset..offset + length]);
+        Ok(PACKET_SIZE)
+    }
 
-            start += length;
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::TrimAll]
+    }
+
+    fn per_transaction(&self) -> bool {
+        true
+    }
+}
 
-            compressed[start] = TrimAll::DELIMITER_BYTE;
-            start += 1;
+impl Lz4 {
+    pub fn new(enc_level: u32) -> Self {
+        Lz4 {
+            enc_level,
+            encoders: Pool::default(),
+            decoders: Pool::default(),
         }
-        Ok(compressed)
     }
+}
 
-    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<Error>> {
-        let mut decompressed = vec![0; PACKET_SIZE];
+impl CompressionAlgo for Lz4 {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        self.encoders.with(lz4f::FrameEncoder::new, |encoder| {
+            encoder.compress(self.enc_level, bytes, buf)
+        })
+    }
 
-        let mut src = 0;
-        let mut dst = 0;
-        let mut delta = 0;
-        let mut index = 0;
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        self.decoders.with(lz4f::FrameDecoder::new, |decoder| {
+            decoder.decompress(bytes, buf, MAX_DECOMPRESSED_SIZE as usize)
+        })
+    }
 
-        for i in 0..bytes.len() {
-            let is_delimiter = bytes[i] == TrimAll::DELIMITER_BYTE;
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::Lz4]
+    }
+}
 
-            if is_delimiter {
-                decompressed[dst..dst + delta].copy_from_slice(&bytes[src..src + delta]);
+impl Lz4Block {
+    /// Upper bound for the announced uncompressed length, so a malformed packet can't make us
+    /// allocate gigabytes.
+    const MAX_BLOCK_SIZE: u64 = 1 << 20;
+
+    /// Like the frame format, levels below 3 use the fast compressor, which can be sped up
+    /// further with an acceleration factor > 1. Levels from 3 on use the high compression mode.
+    pub fn new(enc_level: u32, acceleration: i32) -> Self {
+        Lz4Block {
+            enc_level,
+            acceleration,
+        }
+    }
+}
 
-                index += 1;
-                if index >= self.offsets.len() {
-                    break;
-                }
+impl CompressionAlgo for Lz4Block {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let mode = if self.enc_level >= 3 {
+            CompressionMode::HIGHCOMPRESSION(self.enc_level as i32)
+        } else {
+            CompressionMode::FAST(self.acceleration)
+        };
+
+        let start = buf.len();
+        varint::write(bytes.len() as u64, buf);
+
+        let block_start = buf.len();
+        buf.resize(block_start + block::compress_bound(bytes.len())?, 0);
+        let block_size =
+            block::compress_to_buffer(bytes, Some(mode), false, &mut buf[block_start..])?;
+        buf.truncate(block_start + block_size);
+
+        Ok(buf.len() - start)
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let (size, num_bytes) = varint::read(bytes)?;
+        if size > Lz4Block::MAX_BLOCK_SIZE {
+            return Err(Error::OutputOverflow);
+        }
+
+        let start = buf.len();
+        buf.resize(start + size as usize, 0);
+        let size =
+            block::decompress_to_buffer(&bytes[num_bytes..], Some(size as i32), &mut buf[start..])?;
+        buf.truncate(start + size);
+
+        Ok(size)
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::Lz4Block]
+    }
+}
+
+impl Zstd {
+    pub fn new(enc_level: i32) -> Self {
+        Zstd::with_dictionary(enc_level, Vec::new())
+    }
+
+    pub fn with_dictionary(enc_level: i32, dictionary: Vec<u8>) -> Self {
+        Zstd {
+            enc_level,
+            dictionary,
+            compressors: Pool::default(),
+            decompressors: Pool::default(),
+        }
+    }
+}
+
+impl CompressionAlgo for Zstd {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let create = || {
+            Ok(zstd::bulk::Compressor::with_dictionary(
+                self.enc_level,
+                &self.dictionary,
+            )?)
+        };
+
+        self.compressors.with(create, |compressor| {
+            let start = buf.len();
+            buf.resize(start + zstd::zstd_safe::compress_bound(bytes.len()), 0);
+            let size = compressor.compress_to_buffer(bytes, &mut buf[start..])?;
+            buf.truncate(start + size);
+
+            Ok(size)
+        })
+    }
 
-                dst = self.offsets[index].0 + 1 - self.offsets[index].1;
-                src = i + 1;
-                delta = 0;
-            } else {
-                delta += 1;
+    /// Frames announce their decompressed size, only those of other encoders may not. These are
+    /// streamed instead.
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let size = match zstd::zstd_safe::get_frame_content_size(bytes) {
+            Ok(Some(size)) => size,
+            Ok(None) => {
+                let decoder = zstd::stream::Decoder::with_dictionary(bytes, &self.dictionary)?;
+                return read_limited(decoder, buf);
             }
+            Err(_) => return Err(Error::TruncatedInput),
+        };
+        if size > MAX_DECOMPRESSED_SIZE {
+            return Err(Error::OutputOverflow);
         }
 
-        Ok(decompressed)
+        let create = || Ok(zstd::bulk::Decompressor::with_dictionary(&self.dictionary)?);
+
+        self.decompressors.with(create, |decompressor| {
+            let start = buf.len();
+            buf.resize(start + size as usize, 0);
+            let size = decompressor.decompress_to_buffer(bytes, &mut buf[start..])?;
+            buf.truncate(start + size);
+
+            Ok(size)
+        })
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::Zstd]
     }
 }
 
-impl Lz4 {
-    pub fn new(enc_level: u32) -> Self {
-        Lz4 { enc_level }
+impl Pipeline {
+    pub fn new(stages: Vec<Box<dyn CompressionAlgo>>) -> Self {
+        Pipeline {
+            stages,
+            scratch: Pool::default(),
+        }
+    }
+
+    /// Feeds `bytes` through the stages in the order given, passing the intermediate results in
+    /// the scratch buffers, and appends the output of the last stage to `buf`.
+    fn chain_into<'a>(
+        &self,
+        mut stages: impl DoubleEndedIterator<Item = &'a Box<dyn CompressionAlgo>>,
+        bytes: &[u8],
+        buf: &mut Vec<u8>,
+        step: fn(&dyn CompressionAlgo, &[u8], &mut Vec<u8>) -> Result<usize>,
+    ) -> Result<usize> {
+        let last = match stages.next_back() {
+            Some(last) => last,
+            None => {
+                buf.extend_from_slice(bytes);
+                return Ok(bytes.len());
+            }
+        };
+
+        self.scratch.with(
+            || Ok(Default::default()),
+            |(input, output)| {
+                input.clear();
+                input.extend_from_slice(bytes);
+
+                for stage in stages {
+                    output.clear();
+                    step(stage.as_ref(), input, output)?;
+                    mem::swap(input, output);
+                }
+
+                step(last.as_ref(), input, buf)
+            },
+        )
+    }
+
+    /// The number of leading stages that work on single transactions of a batch.
+    fn num_each_stages(&self) -> usize {
+        self.stages
+            .iter()
+            .take_while(|stage| stage.per_transaction())
+            .count()
     }
 }
 
-impl CompressionAlgo for Lz4 {
-    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<Error>> {
-        let mut encoder = EncoderBuilder::new()
-            .level(self.enc_level)
-            .build(Vec::new())?;
+impl CompressionAlgo for Pipeline {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        self.chain_into(self.stages.iter(), bytes, buf, |stage, bytes, buf| {
+            stage.compress_into(bytes, buf)
+        })
+    }
 
-        encoder.write_all(bytes)?;
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        self.chain_into(self.stages.iter().rev(), bytes, buf, |stage, bytes, buf| {
+            stage.decompress_into(bytes, buf)
+        })
+    }
 
-        let (buf, result) = encoder.finish();
-        result?;
+    fn codec_ids(&self) -> Vec<CodecId> {
+        self.stages
+            .iter()
+            .flat_map(|stage| stage.codec_ids())
+            .collect()
+    }
 
-        Ok(buf)
+    fn per_transaction(&self) -> bool {
+        self.stages.iter().all(|stage| stage.per_transaction())
     }
 
-    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<Error>> {
-        let mut decoder = Decoder::new(Cursor::new(bytes))?;
-        let mut decompr = Vec::new();
+    fn stateful(&self) -> bool {
+        self.stages.iter().any(|stage| stage.stateful())
+    }
 
-        decoder.read_to_end(&mut decompr)?;
+    fn report(&self) -> Option<String> {
+        let reports: Vec<String> = self
+            .stages
+            .iter()
+            .filter_map(|stage| stage.report())
+            .collect();
+        if reports.is_empty() {
+            None
+        } else {
+            Some(reports.join("\n"))
+        }
+    }
+
+    fn compress_each_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let each_stages = &self.stages[..self.num_each_stages()];
+        self.chain_into(each_stages.iter(), bytes, buf, |stage, bytes, buf| {
+            stage.compress_into(bytes, buf)
+        })
+    }
 
-        Ok(decompr)
+    fn compress_joint_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let joint_stages = &self.stages[self.num_each_stages()..];
+        self.chain_into(joint_stages.iter(), bytes, buf, |stage, bytes, buf| {
+            stage.compress_into(bytes, buf)
+        })
     }
 }
+
 const NOT_SIGNATURE_FRAGMENTS: usize = 324;
 
 impl CompressionAlgo for TrimFragment {
-    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<Error>> {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() != PACKET_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+
         // Count 0 bytes with the sig/msg fragment
         let compressed_sigfrag_size = {
             let mut size = SIGNATURE_FRAGMENTS.5;
@@ -170,24 +745,39 @@ impl CompressionAlgo for TrimFragment {
             size
         };
 
-        let mut compressed = vec![0; compressed_sigfrag_size + NOT_SIGNATURE_FRAGMENTS];
-        compressed[0..compressed_sigfrag_size].copy_from_slice(&bytes[0..compressed_sigfrag_size]);
-        compressed[compressed_sigfrag_size..compressed_sigfrag_size + NOT_SIGNATURE_FRAGMENTS]
-            .copy_from_slice(&bytes[SIGNATURE_FRAGMENTS.5..PACKET_SIZE]);
+        buf.extend_from_slice(&bytes[0..compressed_sigfrag_size]);
+        buf.extend_from_slice(&bytes[SIGNATURE_FRAGMENTS.5..PACKET_SIZE]);
 
-        Ok(compressed)
+        Ok(compressed_sigfrag_size + NOT_SIGNATURE_FRAGMENTS)
     }
 
-    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<Error>> {
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() < NOT_SIGNATURE_FRAGMENTS {
+            return Err(Error::TruncatedInput);
+        }
+
         let compressed_sigfrag_size = bytes.len() - NOT_SIGNATURE_FRAGMENTS;
+        if compressed_sigfrag_size > SIGNATURE_FRAGMENTS.5 {
+            return Err(Error::OutputOverflow);
+        }
 
-        let mut decompressed = vec![0; PACKET_SIZE];
+        let start = buf.len();
+        buf.resize(start + PACKET_SIZE, 0);
+        let decompressed = &mut buf[start..];
         decompressed[0..compressed_sigfrag_size]
             .copy_from_slice(&bytes[0..compressed_sigfrag_size]);
         decompressed[SIGNATURE_FRAGMENTS.5..PACKET_SIZE]
             .copy_from_slice(&bytes[compressed_sigfrag_size..bytes.len()]);
 
-        Ok(decompressed)
+        Ok(PACKET_SIZE)
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::TrimFragment]
+    }
+
+    fn per_transaction(&self) -> bool {
+        true
     }
 }
 
@@ -195,12 +785,13 @@ impl CompressionAlgo for TrimFragment {
 mod tests {
     use super::super::model::transaction::*;
     use super::*;
+    use crate::allocations;
 
     // first we need to convert mainnet trytes to ict trytes
     // NOTE: length is already 2754 (instead of 2673 on the mainnet)
     const MAINNET_TRYTES: &str = "SEGQSWYCJHRLJYEGZLRYQAZPLVRAYIWGWJUMFFX99UZUKBQNFYAOQLOFARIKNEBKDRHJJWDJARXTNPHPAODJRSGJBVVYBVJHZALJWDCJHZRSACOVCVVAVHZVTPFTAJWVGFSVLSYXHNNXEGSMJHDBZKGFQNYJJJBAPDHFFGZ9POSOMWTDPGXI9KQRLMUVWNEQDANMXROVORJVALWVGDDJAFOOBXUKVCCIVXSSHZUCZV9XVBASLWX9NXPWGMGYCRD9ILQMKIGPBGGMKAIJKNALBLABATYFVIRBKTXTWNUZAUXRASB9EEIQHWBD9ZYUDBUPBSWXVYXQXECRCHQAYH9ZBUZBASPOIGBSGWJYFKFRITUBVMCYGCMAPTXOIWEVTUXSUOUPTUQOPMMPUTHXMOP9CW9THAZXEPMOMNEOBLUBPOAIOBEBERRZCIKHSTDWUSUPUWNJOCLNZDCEKWWAAJDPJXJEHHSYFN9MH9BGUDQ9CSZBIHRC9PSQJPGKH9ILZDWUWLEKWFKUFFFIMOQKRMKOYXEJHXLCEGCGGKHGJUHOXINSWCKRNMUNAJDCVLZGEBII9ASTYFTDYDZIZSNHIWHSQ9HODQMVNDKMKHCFDXIIGDIVJSBOOE9GRIXCD9ZUTWCUDKFTETSYSRBQABXCXZFOWQMQFXHYZWD9JZXUWHILMRNWXSGUMIIXZYCTWWHCWMSSTCNSQXQXMQPTM9MOQMIVDYNNARDCVNQEDTBKWOIOSKPKPOZHJGJJGNYWQWUWAZMBZJ9XEJMRVRYFQPJ9NOIIXEGIKMMN9DXYQUILRSCSJDIDN9DCTFGQIYWROZQIEQTKMRVLGGDGA9UVZPNRGSVTZYAPMWFUWDEUULSEEGAGITPJQ9DBEYEN9NVJPUWZTOTJHEQIXAPDOICBNNCJVDNM9YRNXMMPCOYHJDUFNCYTZGRCBZKOLHHUK9VOZWHEYQND9WUHDNGFTAS99MRCAU9QOYVUZKTIBDNAAPNEZBQPIRUFUMAWVTCXSXQQIYQPRFDUXCLJNMEIKVAINVCCZROEWEX9XVRM9IHLHQCKC9VLK9ZZWFBJUZKGJCSOPQPFVVAUDLKFJIJKMLZXFBMXLMWRSNDXRMMDLE9VBPUZB9SVLTMHA9DDDANOKIPY9ULDWAKOUDFEDHZDKMU9VMHUSFG9HRGZAZULEJJTEH9SLQDOMZTLVMBCXVNQPNKXRLBOUCCSBZRJCZIUFTFBKFVLKRBPDKLRLZSMMIQNMOZYFBGQFKUJYIJULGMVNFYJWPKPTSMYUHSUEXIPPPPPJTMDQLFFSFJFEPNUBDEDDBPGAOEJGQTHIWISLRDAABO9H9CSIAXPPJYCRFRCIH9TVBZKTCK9SPQZUYMUOKMZYOMPRHRGF9UAKZTZZG9VVVTIHMSNDREUOUOSLKUHTNFXTNSJVPVWCQXUDIMJIAMBPXUGBNDTBYPKYQYJJCDJSCTTWHOJKORLHGKRJMDCMRHSXHHMQBFJWZWHNUHZLYOAFQTRZFXDBYASYKWEVHKYDTJIAUKNCCEPSW9RITZXBOFKBAQOWHKTALQSCHARLUUGXISDMBVEUKOVXTKTEVKLGYVYHPNYWKNLCVETWIHHVTBWT9UPMTQWBZPRPRSISUBIBECVDNIZQULAGLONGVFLVZPBMHJND9CEVIXSYGFZAGGN9MQYOAKMENSEOGCUNKEJTDLEDCD9LGKYANHMZFSSDDZJKTKUJSFL9GYFDICTPJEPDSBXDQTARJQEWUVWDWSQPKIHPJONKHESSQH9FNQEO9WUCFDWPPPTIQPWCVDYTTWPLCJJVYNKE9ZEJNQBEJBMDBLNJKQDOQOHVS9VY9UPSU9KZVDFOESHNRRWBK9EZCYALAUYFGPCEWJQDXFENSNQEAUWDXJGOMCLQUQWMCPHOBZZ9SZJ9KZXSHDLPHPNYMVUJQSQETTN9SG9SIANJHWUYQXZXAJLYHCZYRGITZYQLAAYDVQVNKCDIYWAYBAFBMAYEAEAGMTJGJRSNHBHCEVIQRXEFVWJWOPU9FPDOWIFL9EWGHICRBNRITJDZNYACOGTUDBZYIYZZWAOCDBQFFNTTSTGKECWTVWZSPHX9HNRUYEAEWXENEIDLVVFMZFVPUNHMQPAIOKVIBDIHQIHFGRJOHHONPLGBSJUD9HHDTQQUZN9NVJYOAUMXMMOCNUFLZ9BAJSZMDMPQHPWSFVWOJQDPHV9DYSQPIBL9LYZHQKKOVF9TFVTTXQEUWFQSLGLVTGK99VSUEDXIBIWCQHDQQSQLDHZ9999999999999999999TRINITY99999999999999999999TNXSQ9D99A99999999B99999999MXKZAGDGKVADXOVCAXEQYZGOGQKDLKIUPYXIL9PXYBQXGYDEGNXTFURSWQYLJDFKEV9VVBBQLTLHIBTFYOGBHPUUHS9CKWSAPIMDIRNSUJ9CFPGKTUFAGQYVMFKOZSVAHIFJXWCFBZLICUWF9GNDZWCOWDUIIZ9999OXNRVXLBKJXEZMVABR9UQBVSTBDFSAJVRRNFEJRL9UFTOFPJHQMQKAJHDBIQAETS9OUVTQ9DSPAOZ9999TRINITY99999999999999999999LPZYMWQME999999999MMMMMMMMMDTIZE9999999999999999999999";
 
-    fn get_example_trytes() -> String {
+    pub(super) fn get_example_trytes() -> String {
         let sig_msg_frag = MAINNET_TRYTES.get(0..2187).unwrap();
         let extra_data_digest = MAINNET_TRYTES.get((2187 + 162)..(2187 + 162 + 81)).unwrap(); //copied bundle hash
         let addr_value_tag_timestamps = MAINNET_TRYTES.get(2187..(2187 + 162)).unwrap();
@@ -216,7 +807,7 @@ mod tests {
     fn lz4_compression_works() {
         let lz4 = Lz4::new(0);
         //let tx = TransactionBuilder::default().message("Hello").build();
-        let tx = Transaction::from_tryte_string(&get_example_trytes());
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
         let bytes = tx.as_bytes();
 
         let compressed = lz4
@@ -227,7 +818,104 @@ mod tests {
             .decompress(&compressed)
             .expect("error decompressing transactions bytes");
 
-        let tx2 = Transaction::from_tx_bytes(&decompressed);
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+        assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+    }
+
+    #[test]
+    fn lz4_block_compression_works() {
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        for lz4_block in &[
+            Lz4Block::new(0, 1),
+            Lz4Block::new(0, 8),
+            Lz4Block::new(9, 1),
+        ] {
+            let compressed = lz4_block
+                .compress(&bytes)
+                .expect("error compressing transaction bytes");
+
+            let decompressed = lz4_block
+                .decompress(&compressed)
+                .expect("error decompressing transactions bytes");
+
+            let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+            assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+        }
+    }
+
+    #[test]
+    fn lz4_block_is_smaller_than_lz4_frame() {
+        let tx = Transaction::default().message("Hello").unwrap();
+        let bytes = tx.as_bytes();
+
+        let block = Lz4Block::new(0, 1).compress(&bytes).unwrap();
+        let frame = Lz4::new(0).compress(&bytes).unwrap();
+
+        assert!(block.len() < frame.len());
+        assert!(Lz4Block::new(0, 1)
+            .decompress(&[0xFF, 0xFF, 0xFF, 0x7F])
+            .is_err());
+    }
+
+    #[test]
+    fn zstd_compression_works() {
+        let zstd = Zstd::new(3);
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = zstd
+            .compress(&bytes)
+            .expect("error compressing transaction bytes");
+
+        let decompressed = zstd
+            .decompress(&compressed)
+            .expect("error decompressing transactions bytes");
+
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+        assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+    }
+
+    #[test]
+    fn zstd_rejects_oversized_output() {
+        let zstd = Zstd::new(3);
+        let bytes = vec![0; MAX_DECOMPRESSED_SIZE as usize + 1];
+        let compressed = zstd.compress(&bytes).unwrap();
+
+        assert!(matches!(
+            zstd.decompress(&compressed),
+            Err(Error::OutputOverflow)
+        ));
+        assert_eq!(
+            bytes[1..],
+            zstd.decompress(&zstd.compress(&bytes[1..]).unwrap())
+                .unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn zstd_dictionary_compression_works() {
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        // A raw content dictionary that contains the transaction itself
+        let zstd = Zstd::with_dictionary(3, bytes.to_vec());
+
+        let compressed = zstd
+            .compress(&bytes)
+            .expect("error compressing transaction bytes");
+
+        assert!(compressed.len() < Zstd::new(3).compress(&bytes).unwrap().len());
+
+        let decompressed = zstd
+            .decompress(&compressed)
+            .expect("error decompressing transactions bytes");
+
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
 
         assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
     }
@@ -235,7 +923,7 @@ mod tests {
     #[test]
     fn trim_fragment_compression_works() {
         let trim_frag = TrimFragment;
-        let tx = Transaction::from_tryte_string(&get_example_trytes());
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
         let bytes = tx.as_bytes();
 
         let compressed = trim_frag
@@ -248,7 +936,7 @@ mod tests {
             .decompress(&compressed)
             .expect("error decompressing transactions bytes");
 
-        let tx2 = Transaction::from_tx_bytes(&decompressed);
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
 
         assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
     }
@@ -256,7 +944,7 @@ mod tests {
     #[test]
     fn trim_all_compression_works() {
         let trim_all = TrimAll::new();
-        let tx = Transaction::from_tryte_string(&get_example_trytes());
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
         let bytes = tx.as_bytes();
 
         let compressed = trim_all
@@ -271,7 +959,7 @@ mod tests {
 
         println!("decompressed = {}", decompressed.len());
 
-        let tx2 = Transaction::from_tx_bytes(&decompressed);
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
 
         assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
 
@@ -280,6 +968,227 @@ mod tests {
         //println!("{}", msg);
     }
 
+    #[test]
+    fn trim_fragment_rejects_malformed_input() {
+        let trim_frag = TrimFragment;
+
+        assert!(trim_frag.compress(&[0; 100]).is_err());
+        assert!(trim_frag.decompress(&[0; 100]).is_err());
+        assert!(trim_frag.decompress(&[0; 2000]).is_err());
+    }
+
+    #[test]
+    fn trim_all_rejects_malformed_input() {
+        let trim_all = TrimAll::new();
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let compressed = trim_all.compress(&tx.as_bytes()).unwrap();
+
+        assert!(trim_all.compress(&[0; 100]).is_err());
+        assert!(trim_all.decompress(&[]).is_err());
+        assert!(trim_all
+            .decompress(&compressed[..compressed.len() - 1])
+            .is_err());
+        assert!(trim_all.decompress(&[1; 100]).is_err());
+
+        let mut trailing = compressed.clone();
+        trailing.push(1);
+        assert!(trim_all.decompress(&trailing).is_err());
+    }
+
+    #[test]
+    fn pipeline_compression_works() {
+        let pipeline = Pipeline::new(vec![Box::new(TrimAll::new()), Box::new(Lz4::new(0))]);
+        let tx = Transaction::from_tryte_string(&get_example_trytes())
+            .and_then(|tx| tx.message("Hello"))
+            .unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = pipeline
+            .compress(&bytes)
+            .expect("error compressing transaction bytes");
+
+        let decompressed = pipeline
+            .decompress(&compressed)
+            .expect("error decompressing transactions bytes");
+
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+        assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+    }
+
+    #[test]
+    fn compression_into_buffers_works() {
+        let registry = Registry::new(Vec::new(), &TryteModel::default());
+        let bytes = Transaction::from_tryte_string(&get_example_trytes())
+            .and_then(|tx| tx.message("Hello"))
+            .unwrap()
+            .as_bytes();
+
+        let algos: Vec<Box<dyn CompressionAlgo>> = vec![
+            Box::new(Lz4::new(0)),
+            Box::new(Lz4Block::new(0, 1)),
+            Box::new(TrimFragment),
+            Box::new(TrimAll::new()),
+            Box::new(Sparse),
+            Box::new(Pipeline::new(vec![
+                Box::new(TrimAll::new()),
+                Box::new(Lz4Block::new(0, 1)),
+                Box::new(Lz4::new(0)),
+            ])),
+        ];
+
+        let mut compressed = Vec::new();
+        let mut decompressed = Vec::new();
+
+        // Reuse the buffers across algos and keep what is already in them
+        for algo in &algos {
+            compressed.clear();
+            compressed.push(42);
+            let len = algo.compress_into(&bytes, &mut compressed).unwrap();
+            assert_eq!(compressed.len() - 1, len);
+            assert_eq!(algo.compress(&bytes).unwrap(), &compressed[1..]);
+
+            decompressed.clear();
+            decompressed.push(42);
+            let len = algo
+                .decompress_into(&compressed[1..], &mut decompressed)
+                .unwrap();
+            assert_eq!(PACKET_SIZE, len);
+            assert_eq!(&bytes[..], &decompressed[1..]);
+
+            decompressed.truncate(1);
+            registry
+                .decompress_into(&algo.codec_ids(), &compressed[1..], &mut decompressed)
+                .unwrap();
+            assert_eq!(&bytes[..], &decompressed[1..]);
+        }
+    }
+
+    #[test]
+    fn batch_compression_works() {
+        let registry = Registry::new(Vec::new(), &TryteModel::default());
+        let txs: Vec<Transaction> = ["Hello", "World", "Hello World"]
+            .iter()
+            .map(|msg| {
+                Transaction::from_tryte_string(&get_example_trytes())
+                    .and_then(|tx| tx.message(msg))
+                    .unwrap()
+            })
+            .collect();
+
+        let algos: Vec<Box<dyn CompressionAlgo>> = vec![
+            Box::new(Lz4Block::new(0, 1)),
+            Box::new(TrimAll::new()),
+            Box::new(Pipeline::new(vec![
+                Box::new(TrimAll::new()),
+                Box::new(Lz4Block::new(0, 1)),
+            ])),
+        ];
+
+        for algo in algos {
+            let mut batch = Vec::new();
+            for tx in &txs {
+                let mut record = Vec::new();
+                algo.compress_each_into(&tx.as_bytes(), &mut record)
+                    .unwrap();
+                frame::write_record(&record, &mut batch);
+            }
+            let mut compressed = Vec::new();
+            algo.compress_joint_into(&batch, &mut compressed).unwrap();
+
+            let (mut decompressed, mut ends) = (Vec::new(), Vec::new());
+            registry
+                .decompress_many_into(&algo.codec_ids(), &compressed, &mut decompressed, &mut ends)
+                .expect("error decompressing batch");
+
+            assert_eq!(txs.len(), ends.len());
+            let starts = std::iter::once(0).chain(ends.iter().cloned());
+            for ((tx, start), &end) in txs.iter().zip(starts).zip(&ends) {
+                let tx2 = Transaction::from_tx_bytes(&decompressed[start..end]).unwrap();
+                assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+            }
+        }
+    }
+
+    #[test]
+    fn batch_compression_finds_redundancy_across_transactions() {
+        let lz4 = Lz4Block::new(0, 1);
+        let bytes = Transaction::from_tryte_string(&get_example_trytes())
+            .unwrap()
+            .as_bytes();
+
+        let single = lz4.compress(&bytes).unwrap();
+
+        let mut batch = Vec::new();
+        frame::write_record(&bytes, &mut batch);
+        frame::write_record(&bytes, &mut batch);
+        let mut joint = Vec::new();
+        lz4.compress_joint_into(&batch, &mut joint).unwrap();
+
+        assert!(joint.len() < 2 * single.len());
+    }
+
+    #[test]
+    fn codecs_dont_allocate_once_warmed_up() {
+        let model = TryteModel::default();
+        let registry = Registry::new(Vec::new(), &model);
+        let txs: Vec<_> = ["Hello", "World"]
+            .iter()
+            .map(|msg| {
+                Transaction::from_tryte_string(&get_example_trytes())
+                    .and_then(|tx| tx.message(msg))
+                    .unwrap()
+                    .as_bytes()
+            })
+            .collect();
+
+        // Brotli allocates its state per call, so it is left out
+        let algos: Vec<Box<dyn CompressionAlgo>> = vec![
+            Box::new(Lz4::new(0)),
+            Box::new(Lz4Block::new(0, 1)),
+            Box::new(Zstd::new(3)),
+            Box::new(TrimFragment),
+            Box::new(TrimAll::new()),
+            Box::new(Sparse),
+            Box::new(Pack5),
+            Box::new(HashRef::new()),
+            Box::new(Huffman::new(&model)),
+            Box::new(TimestampDelta),
+            Box::new(Auto::with_candidates(vec![
+                Box::new(TrimAll::new()),
+                Box::new(Lz4::new(0)),
+            ])),
+            Box::new(Pipeline::new(vec![
+                Box::new(TrimAll::new()),
+                Box::new(Lz4Block::new(0, 1)),
+            ])),
+            #[cfg(feature = "snappy")]
+            Box::new(Snappy::new()),
+            #[cfg(feature = "deflate")]
+            Box::new(Deflate::new(6)),
+        ];
+
+        for algo in &algos {
+            let codec_ids = algo.codec_ids();
+            let (mut compressed, mut decompressed) = (Vec::new(), Vec::new());
+            let mut round_trip = |tx: &[u8]| {
+                compressed.clear();
+                decompressed.clear();
+                algo.compress_into(tx, &mut compressed).unwrap();
+                registry
+                    .decompress_into(&codec_ids, &compressed, &mut decompressed)
+                    .unwrap();
+                assert_eq!(tx, &decompressed[..]);
+            };
+
+            for tx in &txs {
+                round_trip(tx);
+            }
+            let num = allocations::count(|| round_trip(&txs[0]));
+            assert_eq!(0, num, "{:?} allocated", codec_ids);
+        }
+    }
+
     #[test]
     fn bench_create_1000_compressions() {
         /*
@@ -0,0 +1,205 @@
+use std::mem;
+use std::sync::Mutex;
+
+use super::*;
+use crate::error::{Error, Result};
+use crate::frame;
+
+/// Compresses every transaction with each candidate codec and keeps the smallest output. The
+/// output is prefixed with the codec ids of the winner, written like the ones of the header.
+///
+/// `HashRef` is never a candidate: it would update its cache for transactions it didn't win.
+pub struct Auto {
+    /// Each candidate with its codec ids.
+    candidates: Vec<(Vec<CodecId>, Box<dyn CompressionAlgo>)>,
+    /// How often each candidate won.
+    wins: Mutex<Vec<usize>>,
+    /// The smallest output so far and the one of the current candidate.
+    scratch: Pool<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Auto {
+    /// Uses every stateless codec with its default settings as candidate.
+    pub fn new(dictionary: Vec<u8>, model: &TryteModel) -> Self {
+        Self::with_candidates(vec![
+            Box::new(TrimFragment),
+            Box::new(TrimAll::new()),
+            Box::new(Sparse),
+            Box::new(Pack5),
+            Box::new(TimestampDelta),
+            Box::new(Huffman::new(model)),
+            Box::new(Lz4::new(0)),
+            Box::new(Lz4Block::new(0, 1)),
+            Box::new(Zstd::with_dictionary(3, dictionary)),
+            #[cfg(feature = "snappy")]
+            Box::new(Snappy::new()),
+            #[cfg(feature = "deflate")]
+            Box::new(Deflate::new(6)),
+            #[cfg(feature = "brotli")]
+            Box::new(Brotli::new(11, 22)),
+        ])
+    }
+
+    pub fn with_candidates(candidates: Vec<Box<dyn CompressionAlgo>>) -> Self {
+        Auto {
+            wins: Mutex::new(vec![0; candidates.len()]),
+            candidates: candidates
+                .into_iter()
+                .map(|candidate| (candidate.codec_ids(), candidate))
+                .collect(),
+            scratch: Pool::default(),
+        }
+    }
+
+    /// How often each candidate won, most frequent first.
+    pub fn wins(&self) -> Vec<(Vec<CodecId>, usize)> {
+        let mut wins: Vec<_> = self
+            .candidates
+            .iter()
+            .zip(self.wins.lock().unwrap().iter())
+            .filter(|(_, &count)| count > 0)
+            .map(|((ids, _), &count)| (ids.clone(), count))
+            .collect();
+        wins.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        wins
+    }
+}
+
+impl CompressionAlgo for Auto {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        self.scratch.with(
+            || Ok(Default::default()),
+            |(best, compressed)| {
+                let mut winner = None;
+                let mut first_error = None;
+
+                for (index, (_, candidate)) in self.candidates.iter().enumerate() {
+                    compressed.clear();
+                    match candidate.compress_into(bytes, compressed) {
+                        Ok(size) => {
+                            // Ties go to the earlier candidate
+                            if winner.is_none() || size < best.len() {
+                                winner = Some(index);
+                                mem::swap(best, compressed);
+                            }
+                        }
+                        Err(e) => {
+                            first_error.get_or_insert(e);
+                        }
+                    }
+                }
+
+                let winner = match winner {
+                    Some(winner) => winner,
+                    None => {
+                        return Err(first_error.unwrap_or(Error::UnknownCodec(CodecId::Auto as u8)))
+                    }
+                };
+
+                let start = buf.len();
+                frame::write_codec_ids(&self.candidates[winner].0, buf);
+                buf.extend_from_slice(best);
+
+                self.wins.lock().unwrap()[winner] += 1;
+
+                Ok(buf.len() - start)
+            },
+        )
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let (&num_codecs, bytes) = bytes.split_first().ok_or(Error::TruncatedInput)?;
+        if bytes.len() < num_codecs as usize {
+            return Err(Error::TruncatedInput);
+        }
+        let (ids, payload) = bytes.split_at(num_codecs as usize);
+
+        let (_, candidate) = self
+            .candidates
+            .iter()
+            .find(|(codec_ids, _)| codec_ids.iter().map(|&id| id as u8).eq(ids.iter().copied()))
+            .ok_or_else(|| Error::UnknownCodec(ids.first().copied().unwrap_or(0)))?;
+
+        candidate.decompress_into(payload, buf)
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::Auto]
+    }
+
+    fn per_transaction(&self) -> bool {
+        true
+    }
+
+    fn report(&self) -> Option<String> {
+        let wins: Vec<String> = self
+            .wins()
+            .iter()
+            .map(|(ids, count)| {
+                let names: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+                format!("{} {}", names.join("+"), count)
+            })
+            .collect();
+
+        Some(format!("Auto wins: {}", wins.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::get_example_trytes;
+    use super::*;
+    use crate::model::transaction::*;
+
+    #[test]
+    fn auto_compression_works() {
+        let auto = Auto::new(Vec::new(), &TryteModel::default());
+        let registry = Registry::new(Vec::new(), &TryteModel::default());
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = auto
+            .compress(&bytes)
+            .expect("error compressing transaction bytes");
+
+        let decompressed = auto
+            .decompress(&compressed)
+            .expect("error decompressing transactions bytes");
+        assert_eq!(&bytes[..], &decompressed[..]);
+
+        let decompressed = registry
+            .decompress(&auto.codec_ids(), &compressed)
+            .expect("error decompressing transactions bytes");
+        assert_eq!(&bytes[..], &decompressed[..]);
+    }
+
+    #[test]
+    fn auto_picks_smallest_output() {
+        let auto = Auto::with_candidates(vec![Box::new(Lz4::new(0)), Box::new(TrimAll::new())]);
+        let bytes = Transaction::default().message("Hello").unwrap().as_bytes();
+
+        let trimmed = TrimAll::new().compress(&bytes).unwrap();
+        let lz4 = Lz4::new(0).compress(&bytes).unwrap();
+        let expected = if trimmed.len() < lz4.len() {
+            CodecId::TrimAll
+        } else {
+            CodecId::Lz4
+        };
+
+        let compressed = auto.compress(&bytes).unwrap();
+        assert_eq!(vec![1, expected as u8], compressed[..2].to_vec());
+        assert_eq!(vec![(vec![expected], 1)], auto.wins());
+    }
+
+    #[test]
+    fn auto_rejects_unknown_tags() {
+        let auto = Auto::with_candidates(vec![Box::new(TrimAll::new())]);
+        let registry = Registry::new(Vec::new(), &TryteModel::default());
+
+        assert!(auto.decompress(&[]).is_err());
+        assert!(auto.decompress(&[1, CodecId::Lz4 as u8, 0]).is_err());
+        assert!(registry
+            .decompress(&[CodecId::Auto], &[1, CodecId::Auto as u8, 0])
+            .is_err());
+    }
+}
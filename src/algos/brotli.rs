@@ -0,0 +1,75 @@
+use std::io::Write;
+
+use ::brotli::{CompressorWriter, Decompressor};
+
+use super::{read_limited, CodecId, CompressionAlgo};
+use crate::error::Result;
+
+const BUFFER_SIZE: usize = 4096;
+
+/// Brotli compression algo. Unlike the other codecs it allocates its encoder and decoder state
+/// for every call, the library has no way to reset them.
+pub struct Brotli {
+    quality: u32,
+    lg_window: u32,
+}
+
+impl Brotli {
+    /// Quality ranges from 0 to 11, the window size is 2^lg_window bytes with lg_window from 10
+    /// to 24. Values outside these ranges are clamped.
+    pub fn new(quality: u32, lg_window: u32) -> Self {
+        Brotli {
+            quality: quality.min(11),
+            lg_window: lg_window.clamp(10, 24),
+        }
+    }
+}
+
+impl CompressionAlgo for Brotli {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let start = buf.len();
+        {
+            let mut encoder =
+                CompressorWriter::new(&mut *buf, BUFFER_SIZE, self.quality, self.lg_window);
+            encoder.write_all(bytes)?;
+        }
+        Ok(buf.len() - start)
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        read_limited(Decompressor::new(bytes, BUFFER_SIZE), buf)
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::Brotli]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::get_example_trytes;
+    use super::*;
+    use crate::model::transaction::*;
+
+    #[test]
+    fn brotli_compression_works() {
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        for &(quality, lg_window) in &[(0, 10), (5, 16), (11, 22)] {
+            let brotli = Brotli::new(quality, lg_window);
+
+            let compressed = brotli
+                .compress(&bytes)
+                .expect("error compressing transaction bytes");
+
+            let decompressed = brotli
+                .decompress(&compressed)
+                .expect("error decompressing transactions bytes");
+
+            let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+            assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+        }
+    }
+}
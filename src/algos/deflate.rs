@@ -0,0 +1,115 @@
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+use super::pool::Pool;
+use super::{CodecId, CompressionAlgo, MAX_DECOMPRESSED_SIZE};
+use crate::error::{Error, Result};
+
+/// How much more output space is reserved whenever the output buffer is full.
+const CHUNK_SIZE: usize = 4096;
+
+/// Raw Deflate (RFC 1951), without zlib or gzip headers. The window is always 32 KiB, which is
+/// larger than any datagram anyway. Reuses its streams, which are reset for every call.
+pub struct Deflate {
+    enc_level: u32,
+    compressors: Pool<Compress>,
+    decompressors: Pool<Decompress>,
+}
+
+impl Deflate {
+    /// Levels range from 0 (store only) to 9 (best compression).
+    pub fn new(enc_level: u32) -> Self {
+        Deflate {
+            enc_level: enc_level.min(9),
+            compressors: Pool::default(),
+            decompressors: Pool::default(),
+        }
+    }
+}
+
+impl CompressionAlgo for Deflate {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let create = || Ok(Compress::new(Compression::new(self.enc_level), false));
+
+        self.compressors.with(create, |compress| {
+            compress.reset();
+            let start = buf.len();
+
+            loop {
+                buf.reserve(CHUNK_SIZE);
+                let input = &bytes[compress.total_in() as usize..];
+                let status = compress
+                    .compress_vec(input, buf, FlushCompress::Finish)
+                    .map_err(|e| Error::Io(e.into()))?;
+                if status == Status::StreamEnd {
+                    return Ok(buf.len() - start);
+                }
+            }
+        })
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        self.decompressors.with(
+            || Ok(Decompress::new(false)),
+            |decompress| {
+                decompress.reset(false);
+                let start = buf.len();
+
+                loop {
+                    if (buf.len() - start) as u64 > MAX_DECOMPRESSED_SIZE {
+                        return Err(Error::OutputOverflow);
+                    }
+
+                    buf.reserve(CHUNK_SIZE);
+                    let input = &bytes[decompress.total_in() as usize..];
+                    let status = decompress
+                        .decompress_vec(input, buf, FlushDecompress::Finish)
+                        .map_err(|e| Error::Io(e.into()))?;
+                    if status == Status::StreamEnd {
+                        return Ok(buf.len() - start);
+                    }
+
+                    // Room left in the output means the input ended before the stream did
+                    if buf.len() < buf.capacity() {
+                        return Err(Error::TruncatedInput);
+                    }
+                }
+            },
+        )
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::Deflate]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::get_example_trytes;
+    use super::*;
+    use crate::model::transaction::*;
+
+    #[test]
+    fn deflate_compression_works() {
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        for &level in &[0, 6, 9] {
+            let deflate = Deflate::new(level);
+
+            let compressed = deflate
+                .compress(&bytes)
+                .expect("error compressing transaction bytes");
+
+            let decompressed = deflate
+                .decompress(&compressed)
+                .expect("error decompressing transactions bytes");
+
+            let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+            assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+            assert!(deflate
+                .decompress(&compressed[..compressed.len() / 2])
+                .is_err());
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::{CodecId, CompressionAlgo};
+use crate::constants::*;
+use crate::error::{Error, Result};
+
+type Hash = [u8; 54];
+
+/// Replaces recently seen hashes (extra data digest, address, trunk and branch) with a one byte
+/// reference into a cache that both endpoints maintain in lockstep.
+///
+/// This codec is stateful: the receiver has to decompress exactly the packets the sender
+/// compressed, in the same order. A lost or reordered packet desynchronizes the caches.
+pub struct HashRef {
+    cache: Mutex<HashCache>,
+}
+
+/// Hash fields in the order they are processed.
+const HASH_FIELDS: [(usize, usize); 4] = [
+    (EXTRA_DATA_DIGEST.4, EXTRA_DATA_DIGEST.5),
+    (ADDRESS.4, ADDRESS.5),
+    (TRUNK_HASH.4, TRUNK_HASH.5),
+    (BRANCH_HASH.4, BRANCH_HASH.5),
+];
+
+/// Tag of a hash that is sent in full. All other tags are cache indices.
+const LITERAL_TAG: u8 = 0xFF;
+const CACHE_CAPACITY: usize = LITERAL_TAG as usize;
+
+/// Most recently used hashes first. Every lookup moves the hash to the front and a full cache
+/// evicts the least recently used hash, so both ends agree on the index of every hash.
+struct HashCache {
+    hashes: VecDeque<Hash>,
+}
+
+impl HashCache {
+    fn new() -> Self {
+        HashCache {
+            hashes: VecDeque::with_capacity(CACHE_CAPACITY + 1),
+        }
+    }
+
+    /// Returns the index the hash had before it was moved to the front.
+    fn find(&mut self, hash: &Hash) -> Option<usize> {
+        let index = self.hashes.iter().position(|h| h == hash)?;
+        self.touch(index);
+        Some(index)
+    }
+
+    fn get(&mut self, index: usize) -> Option<Hash> {
+        let hash = *self.hashes.get(index)?;
+        self.touch(index);
+        Some(hash)
+    }
+
+    fn insert(&mut self, hash: Hash) {
+        self.hashes.push_front(hash);
+        self.hashes.truncate(CACHE_CAPACITY);
+    }
+
+    fn touch(&mut self, index: usize) {
+        if let Some(hash) = self.hashes.remove(index) {
+            self.hashes.push_front(hash);
+        }
+    }
+}
+
+impl HashRef {
+    pub fn new() -> Self {
+        HashRef {
+            cache: Mutex::new(HashCache::new()),
+        }
+    }
+}
+
+impl CompressionAlgo for HashRef {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() != PACKET_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+
+        let start = buf.len();
+        let mut tags = [LITERAL_TAG; HASH_FIELDS.len()];
+        buf.extend_from_slice(&tags);
+
+        let mut src = 0;
+        for (tag, &(offset, length)) in tags.iter_mut().zip(HASH_FIELDS.iter()) {
+            buf.extend_from_slice(&bytes[src..offset]);
+            src = offset + length;
+
+            let mut hash = [0; 54];
+            hash.copy_from_slice(&bytes[offset..offset + length]);
+
+            match cache.find(&hash) {
+                Some(index) => *tag = index as u8,
+                None => {
+                    buf.extend_from_slice(&hash);
+                    cache.insert(hash);
+                }
+            }
+        }
+        buf.extend_from_slice(&bytes[src..]);
+
+        buf[start..start + tags.len()].copy_from_slice(&tags);
+
+        Ok(buf.len() - start)
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() < HASH_FIELDS.len() {
+            return Err(Error::TruncatedInput);
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+
+        let (tags, mut bytes) = bytes.split_at(HASH_FIELDS.len());
+
+        // Validate the whole record before touching the cache. A packet that fails halfway must
+        // leave it as the sender's, or every later reference would resolve to the wrong hash.
+        let mut parts: [(&[u8], Option<&[u8]>); HASH_FIELDS.len()] = Default::default();
+        let mut cache_len = cache.hashes.len();
+        let mut position = 0;
+
+        for ((part, &tag), &(offset, length)) in parts.iter_mut().zip(tags).zip(HASH_FIELDS.iter())
+        {
+            let (before, rest) = split(bytes, offset - position)?;
+            bytes = rest;
+            position = offset + length;
+
+            if tag == LITERAL_TAG {
+                let (literal, rest) = split(bytes, length)?;
+                bytes = rest;
+                cache_len = (cache_len + 1).min(CACHE_CAPACITY);
+                *part = (before, Some(literal));
+            } else if (tag as usize) < cache_len {
+                *part = (before, None);
+            } else {
+                return Err(Error::UnknownReference(tag));
+            }
+        }
+
+        if position + bytes.len() != PACKET_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+
+        for (&(before, literal), &tag) in parts.iter().zip(tags) {
+            buf.extend_from_slice(before);
+
+            let hash = match literal {
+                Some(literal) => {
+                    let mut hash = [0; 54];
+                    hash.copy_from_slice(literal);
+                    cache.insert(hash);
+                    hash
+                }
+                None => cache.get(tag as usize).expect("reference was validated"),
+            };
+            buf.extend_from_slice(&hash);
+        }
+        buf.extend_from_slice(bytes);
+
+        Ok(PACKET_SIZE)
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::HashRef]
+    }
+
+    fn per_transaction(&self) -> bool {
+        true
+    }
+
+    fn stateful(&self) -> bool {
+        true
+    }
+}
+
+fn split(bytes: &[u8], at: usize) -> Result<(&[u8], &[u8])> {
+    if bytes.len() < at {
+        return Err(Error::TruncatedInput);
+    }
+    Ok(bytes.split_at(at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::get_example_trytes;
+    use super::*;
+    use crate::model::transaction::*;
+
+    #[test]
+    fn hash_ref_compression_works() {
+        let sender = HashRef::new();
+        let receiver = HashRef::new();
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+
+        let mut sizes = Vec::new();
+        for msg in &["first", "second", "third"] {
+            let bytes = tx.clone().message(msg).unwrap().as_bytes();
+
+            let compressed = sender
+                .compress(&bytes)
+                .expect("error compressing transaction bytes");
+            sizes.push(compressed.len());
+
+            let decompressed = receiver
+                .decompress(&compressed)
+                .expect("error decompressing transactions bytes");
+
+            assert_eq!(&bytes[..], &decompressed[..]);
+        }
+
+        // All hashes are known after the first transaction
+        assert_eq!(sizes[0] - 4 * 54, sizes[1]);
+        assert_eq!(sizes[1], sizes[2]);
+    }
+
+    #[test]
+    fn hash_ref_evicts_least_recently_used() {
+        let mut cache = HashCache::new();
+        for i in 0..=CACHE_CAPACITY {
+            cache.insert([i as u8; 54]);
+        }
+
+        assert_eq!(CACHE_CAPACITY, cache.hashes.len());
+        assert_eq!(None, cache.find(&[0; 54]));
+        assert_eq!(Some(CACHE_CAPACITY - 1), cache.find(&[1; 54]));
+        assert_eq!(Some(0), cache.find(&[1; 54]));
+    }
+
+    #[test]
+    fn hash_ref_rejects_unknown_references() {
+        let receiver = HashRef::new();
+        let mut packet = vec![0, LITERAL_TAG, LITERAL_TAG, LITERAL_TAG];
+        packet.extend_from_slice(&[0; PACKET_SIZE - 54]);
+
+        assert!(receiver.decompress(&packet).is_err());
+        assert!(receiver.decompress(&packet[..3]).is_err());
+        assert!(HashRef::new().compress(&[0; 100]).is_err());
+    }
+
+    #[test]
+    fn hash_ref_failed_packets_leave_cache_alone() {
+        let receiver = HashRef::new();
+
+        // The literal would be inserted before the unknown reference is found
+        let mut packet = vec![LITERAL_TAG, 5, LITERAL_TAG, LITERAL_TAG];
+        packet.extend_from_slice(&[0; PACKET_SIZE + 54]);
+        assert!(receiver.decompress(&packet).is_err());
+
+        // Truncated after the first two literals
+        let mut packet = vec![LITERAL_TAG; 4];
+        packet.extend_from_slice(&[0; PACKET_SIZE - 100]);
+        assert!(receiver.decompress(&packet).is_err());
+
+        assert!(receiver.cache.lock().unwrap().hashes.is_empty());
+    }
+}
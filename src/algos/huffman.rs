@@ -0,0 +1,407 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::{CodecId, CompressionAlgo};
+use crate::constants::*;
+use crate::convert::bytes;
+use crate::convert::luts::TRYTE_TO_ASCII;
+use crate::error::{Error, Result};
+
+const NUM_SYMBOLS: usize = 27;
+const MAX_CODE_LENGTH: u8 = 24;
+
+/// Tryte histogram of 1000 transactions like the ones the sender generates: the mainnet example
+/// transaction with random alphanumeric messages of the default payload size. Indexed like `TRYTE_TO_ASCII`.
+const DEFAULT_COUNTS: [u64; NUM_SYMBOLS] = [
+    254_563, 135_875, 127_493, 65_134, 143_381, 135_906, 134_632, 128_266, 99_425, 76_649, 65_396,
+    64_852, 61_138, 71_879, 67_069, 68_565, 63_884, 73_424, 63_682, 70_662, 68_249, 60_194,
+    113_594, 132_130, 82_910, 111_222, 132_826,
+];
+
+/// How often each of the 27 trytes occurs. The encoder derives its codes from these counts, so
+/// both endpoints have to use the same model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TryteModel {
+    counts: [u64; NUM_SYMBOLS],
+}
+
+impl Default for TryteModel {
+    fn default() -> Self {
+        TryteModel {
+            counts: DEFAULT_COUNTS,
+        }
+    }
+}
+
+impl TryteModel {
+    pub fn empty() -> Self {
+        TryteModel {
+            counts: [0; NUM_SYMBOLS],
+        }
+    }
+
+    /// Counts the trytes of 2enc9 encoded transaction bytes.
+    pub fn add(&mut self, tx_bytes: &[u8]) {
+        for pair in tx_bytes.chunks_exact(2) {
+            for &symbol in &symbols_from_pair(pair[0], pair[1]) {
+                self.counts[symbol as usize] += 1;
+            }
+        }
+    }
+
+    /// Reads a model file with one line per tryte, e.g. `A 1234`.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut model = TryteModel::empty();
+        let mut seen = [false; NUM_SYMBOLS];
+
+        for line in fs::read_to_string(path)?.lines() {
+            let mut parts = line.split_whitespace();
+            let (tryte, count) = match (parts.next(), parts.next()) {
+                (Some(tryte), Some(count)) => (tryte, count),
+                (None, _) => continue,
+                _ => return Err(invalid("expected a tryte and its count")),
+            };
+
+            let symbol = TRYTE_TO_ASCII
+                .iter()
+                .position(|&c| tryte.as_bytes() == [c])
+                .ok_or_else(|| invalid("unknown tryte"))?;
+
+            model.counts[symbol] = count.parse().map_err(|_| invalid("invalid count"))?;
+            seen[symbol] = true;
+        }
+
+        if seen.iter().all(|&s| s) {
+            Ok(model)
+        } else {
+            Err(invalid("missing trytes"))
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let lines: Vec<String> = TRYTE_TO_ASCII
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(&c, count)| format!("{} {}", c as char, count))
+            .collect();
+
+        fs::write(path, lines.join("\n") + "\n")
+    }
+}
+
+/// Static Huffman coder over the 27 trytes of a transaction. Works best on transactions with
+/// lots of empty (`9`) trytes and skewed message fragments, where LZ4 finds few repetitions.
+pub struct Huffman {
+    /// Code length and code of each symbol.
+    codes: [(u8, u32); NUM_SYMBOLS],
+    /// Symbols sorted by code length and then by value, which is the order of canonical codes.
+    sorted_symbols: Vec<u8>,
+    /// Number of codes of each length.
+    length_counts: [u32; MAX_CODE_LENGTH as usize + 1],
+}
+
+impl Huffman {
+    pub fn new(model: &TryteModel) -> Self {
+        let lengths = code_lengths(&model.counts);
+
+        let mut sorted_symbols: Vec<u8> = (0..NUM_SYMBOLS as u8).collect();
+        sorted_symbols.sort_by_key(|&symbol| (lengths[symbol as usize], symbol));
+
+        let mut length_counts = [0; MAX_CODE_LENGTH as usize + 1];
+        for &length in lengths.iter() {
+            length_counts[length as usize] += 1;
+        }
+
+        // Canonical codes: consecutive values within a length, shifted left for longer codes
+        let mut codes = [(0, 0); NUM_SYMBOLS];
+        let mut code = 0u32;
+        let mut prev_length = lengths[sorted_symbols[0] as usize];
+        for &symbol in &sorted_symbols {
+            let length = lengths[symbol as usize];
+            code <<= length - prev_length;
+            codes[symbol as usize] = (length, code);
+            code += 1;
+            prev_length = length;
+        }
+
+        Huffman {
+            codes,
+            sorted_symbols,
+            length_counts,
+        }
+    }
+
+    fn decode_symbol(&self, bits: &mut BitReader) -> Result<u8> {
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+
+        for length in 1..=MAX_CODE_LENGTH as usize {
+            code |= bits.read()?;
+            let count = self.length_counts[length];
+            if code < first + count {
+                return Ok(self.sorted_symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(Error::InvalidTryte)
+    }
+}
+
+impl CompressionAlgo for Huffman {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() != PACKET_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+        if !bytes::is_valid_2enc9(bytes) {
+            return Err(Error::InvalidTryte);
+        }
+
+        let start = buf.len();
+        let mut bits = BitWriter::new(buf);
+        for pair in bytes.chunks_exact(2) {
+            for &symbol in &symbols_from_pair(pair[0], pair[1]) {
+                let (length, code) = self.codes[symbol as usize];
+                bits.write(code, length);
+            }
+        }
+        bits.finish();
+
+        Ok(buf.len() - start)
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let mut bits = BitReader::new(bytes);
+
+        for _ in 0..TRANSACTION_SIZE_TRYTES / 3 {
+            let i0 = self.decode_symbol(&mut bits)?;
+            let i1 = self.decode_symbol(&mut bits)?;
+            let i2 = self.decode_symbol(&mut bits)?;
+
+            buf.push(i0 * 8 + i2 % 8);
+            buf.push(i1 * 8 + i2 / 8);
+        }
+
+        if bits.remaining_bytes() > 0 {
+            return Err(Error::OutputOverflow);
+        }
+
+        Ok(PACKET_SIZE)
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::Huffman]
+    }
+
+    fn per_transaction(&self) -> bool {
+        true
+    }
+}
+
+/// Splits a 2enc9 byte pair into its three trytes.
+fn symbols_from_pair(b0: u8, b1: u8) -> [u8; 3] {
+    [b0 / 8, b1 / 8, b0 % 8 + 8 * (b1 % 8)]
+}
+
+/// Huffman code lengths for the given counts. Every symbol gets a code, even if it was never
+/// counted. Ties are broken by symbol value to make the result deterministic.
+fn code_lengths(counts: &[u64; NUM_SYMBOLS]) -> [u8; NUM_SYMBOLS] {
+    let mut counts: Vec<u64> = counts.iter().map(|&c| c.max(1)).collect();
+
+    loop {
+        // Nodes are (weight, tie breaker, symbols below this node)
+        let mut heap: BinaryHeap<Reverse<(u64, usize, Vec<usize>)>> = counts
+            .iter()
+            .enumerate()
+            .map(|(symbol, &count)| Reverse((count, symbol, vec![symbol])))
+            .collect();
+
+        let mut lengths = [0u8; NUM_SYMBOLS];
+        while heap.len() > 1 {
+            let Reverse((w0, t0, s0)) = heap.pop().unwrap();
+            let Reverse((w1, t1, s1)) = heap.pop().unwrap();
+
+            for &symbol in s0.iter().chain(s1.iter()) {
+                lengths[symbol] += 1;
+            }
+
+            let symbols = s0.into_iter().chain(s1).collect();
+            heap.push(Reverse((w0 + w1, t0.min(t1), symbols)));
+        }
+
+        if lengths.iter().all(|&length| length <= MAX_CODE_LENGTH) {
+            return lengths;
+        }
+
+        // Flatten the distribution until the longest code fits
+        for count in counts.iter_mut() {
+            *count = (*count / 2).max(1);
+        }
+    }
+}
+
+/// Appends bits to a buffer, most significant bit first.
+struct BitWriter<'a> {
+    bytes: &'a mut Vec<u8>,
+    current: u64,
+    num_bits: u8,
+}
+
+impl<'a> BitWriter<'a> {
+    fn new(bytes: &'a mut Vec<u8>) -> Self {
+        BitWriter {
+            bytes,
+            current: 0,
+            num_bits: 0,
+        }
+    }
+
+    fn write(&mut self, code: u32, length: u8) {
+        self.current = (self.current << length) | u64::from(code);
+        self.num_bits += length;
+
+        while self.num_bits >= 8 {
+            self.num_bits -= 8;
+            self.bytes.push((self.current >> self.num_bits) as u8);
+        }
+    }
+
+    /// Pads the last byte with zero bits.
+    fn finish(self) {
+        if self.num_bits > 0 {
+            self.bytes.push((self.current << (8 - self.num_bits)) as u8);
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        BitReader { bytes, position: 0 }
+    }
+
+    fn read(&mut self) -> Result<u32> {
+        let byte = self
+            .bytes
+            .get(self.position / 8)
+            .ok_or(Error::TruncatedInput)?;
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+
+        Ok(u32::from(bit))
+    }
+
+    /// Bytes that weren't touched yet. The padding bits of the last byte don't count.
+    fn remaining_bytes(&self) -> usize {
+        self.bytes.len() - self.position.div_ceil(8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::get_example_trytes;
+    use super::*;
+    use crate::model::transaction::*;
+    use rand::distributions::Alphanumeric;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::iter;
+
+    #[test]
+    fn huffman_compression_works() {
+        let huffman = Huffman::new(&TryteModel::default());
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = huffman
+            .compress(&bytes)
+            .expect("error compressing transaction bytes");
+
+        let decompressed = huffman
+            .decompress(&compressed)
+            .expect("error decompressing transactions bytes");
+
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+        assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+    }
+
+    #[test]
+    fn huffman_beats_lz4_on_message_transactions() {
+        let huffman = Huffman::new(&TryteModel::default());
+        // Random text, like the sender generates, leaves LZ4 nothing to match. Seeded, so that a
+        // failure can be reproduced.
+        let mut rng = StdRng::seed_from_u64(10);
+        let msg: String = iter::repeat(())
+            .map(|()| rng.sample(Alphanumeric))
+            .take(MAX_MESSAGE_LENGTH)
+            .collect();
+        let tx = Transaction::from_tryte_string(&get_example_trytes())
+            .and_then(|tx| tx.message(&msg))
+            .unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = huffman.compress(&bytes).unwrap();
+        let lz4_compressed = super::super::Lz4Block::new(0, 1).compress(&bytes).unwrap();
+
+        assert!(compressed.len() < lz4_compressed.len());
+        assert_eq!(&bytes[..], &huffman.decompress(&compressed).unwrap()[..]);
+    }
+
+    #[test]
+    fn huffman_limits_code_lengths() {
+        // Fibonacci counts produce the deepest possible trees
+        let mut counts = [1u64; NUM_SYMBOLS];
+        for i in 2..NUM_SYMBOLS {
+            counts[i] = counts[i - 1] + counts[i - 2];
+        }
+
+        let lengths = code_lengths(&counts);
+        assert!(lengths.iter().all(|&l| l > 0 && l <= MAX_CODE_LENGTH));
+
+        let huffman = Huffman::new(&TryteModel { counts });
+        let bytes = Transaction::default().as_bytes();
+        let compressed = huffman.compress(&bytes).unwrap();
+        assert_eq!(&bytes[..], &huffman.decompress(&compressed).unwrap()[..]);
+    }
+
+    #[test]
+    fn huffman_rejects_malformed_input() {
+        let huffman = Huffman::new(&TryteModel::default());
+        let bytes = Transaction::default().as_bytes();
+        let mut compressed = huffman.compress(&bytes).unwrap();
+
+        assert!(huffman.compress(&[0xFF; PACKET_SIZE]).is_err());
+        assert!(huffman
+            .decompress(&compressed[..compressed.len() - 1])
+            .is_err());
+
+        compressed.push(0);
+        assert!(huffman.decompress(&compressed).is_err());
+    }
+
+    #[test]
+    fn tryte_model_save_load() {
+        let mut model = TryteModel::empty();
+        model.add(&Transaction::default().message("Hello").unwrap().as_bytes());
+
+        let path = std::env::temp_dir().join("itxc_tryte_model_test.txt");
+        model.save(&path).unwrap();
+        let loaded = TryteModel::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(model, loaded);
+    }
+}
@@ -0,0 +1,312 @@
+//! Reusable LZ4 frame contexts on top of the raw liblz4 bindings, as the `lz4` crate builds a new
+//! context for every encoder and decoder. Every `unsafe` call hands liblz4 a pointer and the size
+//! of the memory behind it, and the length of a `Vec` only grows by what liblz4 reports to have
+//! written into its spare capacity.
+
+use std::ptr;
+
+use lz4::liblz4::*;
+
+use crate::error::{Error, Result};
+
+/// Largest frame header `LZ4F_compressBegin` writes. `LZ4F_compressBound` doesn't include it.
+const MAX_HEADER_SIZE: usize = 19;
+
+/// How much the decoder grows the output by when it runs out of room.
+const CHUNK_SIZE: usize = 4096;
+
+/// An LZ4 frame compression context that is created once and reused for every frame. Writes the
+/// same frames as `lz4::EncoderBuilder` with its default settings.
+pub struct FrameEncoder {
+    ctx: LZ4FCompressionContext,
+}
+
+impl FrameEncoder {
+    pub fn new() -> Result<Self> {
+        let mut ctx = LZ4FCompressionContext(ptr::null_mut());
+        // SAFETY: `ctx` is a valid place for liblz4 to store the new context in. On success it is
+        // owned by the encoder and freed exactly once on drop.
+        check_error(unsafe { LZ4F_createCompressionContext(&mut ctx, LZ4F_VERSION) })?;
+
+        Ok(FrameEncoder { ctx })
+    }
+
+    /// Appends a complete frame holding `bytes` to `buf` and returns its length.
+    pub fn compress(&mut self, level: u32, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let preferences = preferences(level);
+        // SAFETY: Only reads the preferences, which live until the call returns.
+        let bound = check_error(unsafe { LZ4F_compressBound(bytes.len(), &preferences) })?;
+
+        let start = buf.len();
+        buf.reserve(MAX_HEADER_SIZE + bound);
+
+        // SAFETY: Each call writes at most the given capacity left behind `len` and returns how
+        // much it wrote, or fails without the length being changed. So `set_len` only covers
+        // bytes initialized by liblz4, and `len` never passes the capacity. `bytes` is only read.
+        unsafe {
+            let mut len = start;
+
+            len += check_error(LZ4F_compressBegin(
+                self.ctx,
+                buf.as_mut_ptr().add(len),
+                buf.capacity() - len,
+                &preferences,
+            ))?;
+            len += check_error(LZ4F_compressUpdate(
+                self.ctx,
+                buf.as_mut_ptr().add(len),
+                buf.capacity() - len,
+                bytes.as_ptr(),
+                bytes.len(),
+                ptr::null(),
+            ))?;
+            len += check_error(LZ4F_compressEnd(
+                self.ctx,
+                buf.as_mut_ptr().add(len),
+                buf.capacity() - len,
+                ptr::null(),
+            ))?;
+
+            buf.set_len(len);
+        }
+
+        Ok(buf.len() - start)
+    }
+}
+
+impl Drop for FrameEncoder {
+    fn drop(&mut self) {
+        // SAFETY: The context was created in `new` and isn't used after this.
+        unsafe { LZ4F_freeCompressionContext(self.ctx) };
+    }
+}
+
+/// An LZ4 frame decompression context that is created once and reused for every frame.
+pub struct FrameDecoder {
+    ctx: LZ4FDecompressionContext,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Result<Self> {
+        let mut ctx = LZ4FDecompressionContext(ptr::null_mut());
+        // SAFETY: `ctx` is a valid place for liblz4 to store the new context in. On success it is
+        // owned by the decoder and freed exactly once on drop.
+        check_error(unsafe { LZ4F_createDecompressionContext(&mut ctx, LZ4F_VERSION) })?;
+
+        Ok(FrameDecoder { ctx })
+    }
+
+    /// Decompresses the single frame in `bytes`, appends it to `buf` and returns its length.
+    /// Fails if the frame holds more than `limit` bytes or if anything follows it.
+    pub fn decompress(&mut self, bytes: &[u8], buf: &mut Vec<u8>, limit: usize) -> Result<usize> {
+        // A previous frame may have failed halfway through
+        // SAFETY: The context is valid, resetting it touches nothing else.
+        unsafe { LZ4F_resetDecompressionContext(self.ctx) };
+
+        let start = buf.len();
+        let mut src = 0;
+
+        loop {
+            buf.reserve(CHUNK_SIZE);
+
+            let mut src_size = bytes.len() - src;
+            let mut dst_size = buf.capacity() - buf.len();
+
+            // SAFETY: liblz4 reads at most `src_size` bytes of `bytes` behind `src` and writes at
+            // most `dst_size` bytes into the spare capacity of `buf`, then updates both sizes to
+            // what it actually consumed and wrote. `set_len` only runs on success and only covers
+            // the written bytes.
+            let hint = unsafe {
+                let hint = check_error(LZ4F_decompress(
+                    self.ctx,
+                    buf.as_mut_ptr().add(buf.len()),
+                    &mut dst_size,
+                    bytes[src..].as_ptr(),
+                    &mut src_size,
+                    ptr::null(),
+                ))?;
+                buf.set_len(buf.len() + dst_size);
+                hint
+            };
+            src += src_size;
+
+            if buf.len() - start > limit {
+                return Err(Error::OutputOverflow);
+            }
+
+            // The end of the frame
+            if hint == 0 {
+                break;
+            }
+
+            if src == bytes.len() && dst_size == 0 {
+                return Err(Error::TruncatedInput);
+            }
+        }
+
+        if src != bytes.len() {
+            return Err(Error::OutputOverflow);
+        }
+
+        Ok(buf.len() - start)
+    }
+}
+
+impl Drop for FrameDecoder {
+    fn drop(&mut self) {
+        // SAFETY: The context was created in `new` and isn't used after this.
+        unsafe { LZ4F_freeDecompressionContext(self.ctx) };
+    }
+}
+
+fn preferences(level: u32) -> LZ4FPreferences {
+    LZ4FPreferences {
+        frame_info: LZ4FFrameInfo {
+            block_size_id: BlockSize::Default,
+            block_mode: BlockMode::Linked,
+            content_checksum_flag: ContentChecksum::ChecksumEnabled,
+            frame_type: FrameType::Frame,
+            content_size: 0,
+            dict_id: 0,
+            block_checksum_flag: BlockChecksum::BlockChecksumEnabled,
+        },
+        compression_level: level,
+        auto_flush: 0,
+        favor_dec_speed: 0,
+        reserved: [0; 3],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use lz4::{Decoder, EncoderBuilder};
+
+    use super::*;
+
+    #[test]
+    fn frames_match_the_lz4_crate() {
+        let bytes: Vec<u8> = (0..100_000u32).map(|i| (i % 251 / 7) as u8).collect();
+        let mut encoder = FrameEncoder::new().unwrap();
+        let mut decoder = FrameDecoder::new().unwrap();
+
+        let mut lz4_encoder = EncoderBuilder::new().level(4).build(Vec::new()).unwrap();
+        lz4_encoder.write_all(&bytes).unwrap();
+        let expected = lz4_encoder.finish().0;
+
+        let mut compressed = vec![1, 2, 3];
+        let len = encoder.compress(4, &bytes, &mut compressed).unwrap();
+        assert_eq!(expected.len(), len);
+        assert_eq!(&expected[..], &compressed[3..]);
+
+        let mut decompressed = Vec::new();
+        Decoder::new(&compressed[3..])
+            .unwrap()
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(bytes, decompressed);
+
+        let mut decompressed = vec![4];
+        let len = decoder
+            .decompress(&compressed[3..], &mut decompressed, bytes.len())
+            .unwrap();
+        assert_eq!(bytes.len(), len);
+        assert_eq!(&bytes[..], &decompressed[1..]);
+    }
+
+    #[test]
+    fn decoder_rejects_malformed_frames() {
+        let bytes = vec![7; 10_000];
+        let mut compressed = Vec::new();
+        FrameEncoder::new()
+            .unwrap()
+            .compress(0, &bytes, &mut compressed)
+            .unwrap();
+        let mut decoder = FrameDecoder::new().unwrap();
+        let mut buf = Vec::new();
+
+        assert!(decoder
+            .decompress(&compressed, &mut buf, bytes.len() - 1)
+            .is_err());
+        assert!(decoder
+            .decompress(&compressed[..compressed.len() - 1], &mut buf, bytes.len())
+            .is_err());
+        assert!(decoder
+            .decompress(&compressed[..3], &mut buf, bytes.len())
+            .is_err());
+
+        let mut trailing = compressed.clone();
+        trailing.push(0);
+        assert!(decoder
+            .decompress(&trailing, &mut buf, bytes.len())
+            .is_err());
+
+        // The context is still usable after errors
+        buf.clear();
+        decoder
+            .decompress(&compressed, &mut buf, bytes.len())
+            .unwrap();
+        assert_eq!(bytes, buf);
+    }
+
+    #[test]
+    fn encoder_fills_buffers_to_the_brim() {
+        let mut encoder = FrameEncoder::new().unwrap();
+        let mut decoder = FrameDecoder::new().unwrap();
+
+        // Full buffers and empty inputs, many frames on the same contexts
+        for len in (0..3000).step_by(97) {
+            let bytes: Vec<u8> = (0..len).map(|i| (i * 31 % 256) as u8).collect();
+            let mut compressed = vec![9; 5];
+            compressed.shrink_to_fit();
+
+            let written = encoder.compress(0, &bytes, &mut compressed).unwrap();
+            assert_eq!(5 + written, compressed.len());
+            assert!(compressed.len() <= compressed.capacity());
+
+            let mut decompressed = vec![8; 7];
+            decompressed.shrink_to_fit();
+            let read = decoder
+                .decompress(&compressed[5..], &mut decompressed, bytes.len())
+                .unwrap();
+            assert_eq!(len, read);
+            assert_eq!(&[8; 7], &decompressed[..7]);
+            assert_eq!(bytes, &decompressed[7..]);
+        }
+    }
+
+    #[test]
+    fn decoder_survives_mutated_frames() {
+        let bytes: Vec<u8> = (0..20_000u32).map(|i| (i % 13 * i % 97) as u8).collect();
+        let mut compressed = Vec::new();
+        FrameEncoder::new()
+            .unwrap()
+            .compress(0, &bytes, &mut compressed)
+            .unwrap();
+        let mut decoder = FrameDecoder::new().unwrap();
+        let limit = 30_000;
+
+        // Every truncation, and every byte flipped, stays within the limit and the buffer
+        for end in 0..compressed.len() {
+            let mut buf = Vec::new();
+            assert!(decoder
+                .decompress(&compressed[..end], &mut buf, limit)
+                .is_err());
+            assert!(buf.len() <= limit + CHUNK_SIZE);
+        }
+        for i in 0..compressed.len() {
+            let mut mutated = compressed.clone();
+            mutated[i] ^= 0x5A;
+            let mut buf = Vec::new();
+            if let Ok(len) = decoder.decompress(&mutated, &mut buf, limit) {
+                assert_eq!(len, buf.len());
+                assert!(len <= limit);
+            }
+        }
+
+        let mut buf = Vec::new();
+        decoder.decompress(&compressed, &mut buf, limit).unwrap();
+        assert_eq!(bytes, buf);
+    }
+}
@@ -0,0 +1,128 @@
+use super::{CodecId, CompressionAlgo};
+use crate::constants::*;
+use crate::convert::{bytes, trits};
+use crate::error::{Error, Result};
+
+/// Packs 5 trits into every byte (3^5 = 243 states) instead of the 9 trits per 2 bytes of the
+/// 2enc9 encoding. Doesn't trim anything, but is a good first stage for entropy coders.
+pub struct Pack5;
+
+const TRITS_PER_BYTE: usize = 5;
+const PACKED_SIZE: usize = TRANSACTION_SIZE_TRITS.div_ceil(TRITS_PER_BYTE); // =1604
+
+impl CompressionAlgo for Pack5 {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() != PACKET_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+        if !bytes::is_valid_2enc9(bytes) {
+            return Err(Error::InvalidTryte);
+        }
+
+        let trits = trits::from_tx_bytes_2enc9(bytes);
+
+        buf.extend(trits.chunks(TRITS_PER_BYTE).map(|chunk| {
+            // The last chunk only has 4 trits, the missing one counts as 0
+            chunk
+                .iter()
+                .rev()
+                .fold(0, |packed, &trit| packed * 3 + (trit + 1) as u8)
+        }));
+
+        Ok(PACKED_SIZE)
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() < PACKED_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+        if bytes.len() > PACKED_SIZE {
+            return Err(Error::OutputOverflow);
+        }
+
+        let mut trits = [0i8; TRANSACTION_SIZE_TRITS];
+
+        for (chunk, &packed) in trits.chunks_mut(TRITS_PER_BYTE).zip(bytes) {
+            // Values the chunk can't hold would decode to the same trits as a smaller one, so the
+            // last byte must stay below 3^4
+            if u16::from(packed) >= 3u16.pow(chunk.len() as u32) {
+                return Err(Error::InvalidTryte);
+            }
+
+            let mut packed = packed;
+            for trit in chunk.iter_mut() {
+                *trit = (packed % 3) as i8 - 1;
+                packed /= 3;
+            }
+        }
+
+        buf.extend_from_slice(&bytes::from_tx_trits_2enc9(&trits));
+
+        Ok(PACKET_SIZE)
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::Pack5]
+    }
+
+    fn per_transaction(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::get_example_trytes;
+    use super::super::{Lz4, Pipeline};
+    use super::*;
+    use crate::model::transaction::*;
+
+    #[test]
+    fn pack5_compression_works() {
+        let pack5 = Pack5;
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = pack5
+            .compress(&bytes)
+            .expect("error compressing transaction bytes");
+
+        assert_eq!(1604, compressed.len());
+
+        let decompressed = pack5
+            .decompress(&compressed)
+            .expect("error decompressing transactions bytes");
+
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+        assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+    }
+
+    #[test]
+    fn pack5_works_as_pipeline_stage() {
+        let pipeline = Pipeline::new(vec![Box::new(Pack5), Box::new(Lz4::new(0))]);
+        let tx = Transaction::default().message("Hello").unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = pipeline.compress(&bytes).unwrap();
+        let decompressed = pipeline.decompress(&compressed).unwrap();
+
+        assert_eq!(&bytes[..], &decompressed[..]);
+    }
+
+    #[test]
+    fn pack5_rejects_malformed_input() {
+        assert!(Pack5.compress(&[0; 100]).is_err());
+        assert!(Pack5.compress(&[0xFF; PACKET_SIZE]).is_err());
+        assert!(Pack5.decompress(&[0; 100]).is_err());
+        assert!(Pack5.decompress(&[0; PACKED_SIZE + 1]).is_err());
+        assert!(Pack5.decompress(&[0xFF; PACKED_SIZE]).is_err());
+
+        // The last byte only holds 4 trits
+        let mut packed = Pack5.compress(&Transaction::default().as_bytes()).unwrap();
+        packed[PACKED_SIZE - 1] = 81;
+        assert!(Pack5.decompress(&packed).is_err());
+        packed[PACKED_SIZE - 1] = 80;
+        assert!(Pack5.decompress(&packed).is_ok());
+    }
+}
@@ -0,0 +1,169 @@
+use rayon::prelude::*;
+
+use super::CompressionAlgo;
+use crate::error::Result;
+
+/// Compresses each input on its own, spread across all cores. The outputs are in the order of
+/// the inputs. Stateful codecs get the inputs one after another on the calling thread instead.
+pub fn compress_batch<T>(algo: &dyn CompressionAlgo, inputs: &[T]) -> Result<Vec<Vec<u8>>>
+where
+    T: AsRef<[u8]> + Sync,
+{
+    if algo.stateful() {
+        return inputs
+            .iter()
+            .map(|input| algo.compress(input.as_ref()))
+            .collect();
+    }
+
+    inputs
+        .par_iter()
+        .map(|input| algo.compress(input.as_ref()))
+        .collect()
+}
+
+/// Decompresses each input on its own, spread across all cores. The outputs are in the order of
+/// the inputs. Stateful codecs get the inputs one after another on the calling thread instead.
+pub fn decompress_batch<T>(algo: &dyn CompressionAlgo, inputs: &[T]) -> Result<Vec<Vec<u8>>>
+where
+    T: AsRef<[u8]> + Sync,
+{
+    if algo.stateful() {
+        return inputs
+            .iter()
+            .map(|input| algo.decompress(input.as_ref()))
+            .collect();
+    }
+
+    inputs
+        .par_iter()
+        .map(|input| algo.decompress(input.as_ref()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::get_example_trytes;
+    use super::super::*;
+    use super::*;
+    use crate::model::transaction::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Passes bytes through, but first waits a while for a second caller to arrive.
+    #[derive(Default)]
+    struct Rendezvous {
+        inside: AtomicUsize,
+        /// The most callers seen inside at once.
+        most_inside: Arc<AtomicUsize>,
+    }
+
+    impl CompressionAlgo for Rendezvous {
+        fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+            let inside = self.inside.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_inside.fetch_max(inside, Ordering::SeqCst);
+
+            let start = Instant::now();
+            while self.most_inside.load(Ordering::SeqCst) < 2
+                && start.elapsed() < Duration::from_secs(1)
+            {
+                thread::yield_now();
+                let inside = self.inside.load(Ordering::SeqCst);
+                self.most_inside.fetch_max(inside, Ordering::SeqCst);
+            }
+
+            self.inside.fetch_sub(1, Ordering::SeqCst);
+            buf.extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+            buf.extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn codec_ids(&self) -> Vec<CodecId> {
+            Vec::new()
+        }
+    }
+
+    fn get_example_batch() -> Vec<Vec<u8>> {
+        (0..200)
+            .map(|i| {
+                Transaction::from_tryte_string(&get_example_trytes())
+                    .and_then(|tx| tx.message(&format!("Transaction {}", i)))
+                    .unwrap()
+                    .as_bytes()
+                    .to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batch_compression_keeps_the_order() {
+        let txs = get_example_batch();
+        let algo = Pipeline::new(vec![Box::new(TrimAll::new()), Box::new(Lz4::new(0))]);
+
+        let compressed = compress_batch(&algo, &txs).unwrap();
+        for (tx, compressed) in txs.iter().zip(&compressed) {
+            assert_eq!(&algo.compress(tx).unwrap(), compressed);
+        }
+
+        assert_eq!(txs, decompress_batch(&algo, &compressed).unwrap());
+    }
+
+    #[test]
+    fn batch_compression_keeps_stateful_codecs_in_lockstep() {
+        let txs = get_example_batch();
+        let sender = HashRef::new();
+        let receiver = HashRef::new();
+
+        let compressed = compress_batch(&sender, &txs).unwrap();
+        assert_eq!(txs, decompress_batch(&receiver, &compressed).unwrap());
+    }
+
+    #[test]
+    fn batch_compression_reports_errors() {
+        let mut txs = get_example_batch();
+        txs[100].pop();
+
+        assert!(compress_batch(&TrimAll::new(), &txs).is_err());
+    }
+
+    #[test]
+    fn shared_pipelines_run_in_parallel() {
+        let rendezvous = Rendezvous::default();
+        let most_inside = Arc::clone(&rendezvous.most_inside);
+        let algo = Pipeline::new(vec![
+            Box::new(TrimAll::new()),
+            Box::new(rendezvous),
+            Box::new(Lz4::new(0)),
+        ]);
+        let txs = get_example_batch();
+
+        // Two threads are inside the same pipeline at once, they don't wait for each other
+        thread::scope(|scope| {
+            for tx in &txs[..2] {
+                let algo = &algo;
+                scope.spawn(move || {
+                    assert_eq!(tx, &algo.decompress(&algo.compress(tx).unwrap()).unwrap())
+                });
+            }
+        });
+        assert_eq!(2, most_inside.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn batch_compression_matches_one_by_one() {
+        let txs: Vec<Vec<u8>> = (0..10).flat_map(|_| get_example_batch()).collect();
+        let algo = Pipeline::new(vec![Box::new(TrimAll::new()), Box::new(Lz4::new(9))]);
+
+        let sequential: Vec<Vec<u8>> = txs.iter().map(|tx| algo.compress(tx).unwrap()).collect();
+        let parallel = compress_batch(&algo, &txs).unwrap();
+
+        assert_eq!(sequential, parallel);
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Mutex;
+
+use crate::error::Result;
+
+/// Keeps things that are costly to create, like codec contexts and scratch buffers, for reuse.
+/// Every caller takes one of its own, so the lock is only held to take or return it and codecs
+/// shared between threads don't run one at a time.
+pub struct Pool<T> {
+    items: Mutex<Vec<T>>,
+}
+
+impl<T> Pool<T> {
+    /// Runs `f` on an item from the pool, or on a new one from `create` if all are in use, and
+    /// returns the item to the pool afterwards.
+    pub fn with<R>(
+        &self,
+        create: impl FnOnce() -> Result<T>,
+        f: impl FnOnce(&mut T) -> Result<R>,
+    ) -> Result<R> {
+        let item = self.items.lock().unwrap().pop();
+        let mut item = match item {
+            Some(item) => item,
+            None => create()?,
+        };
+
+        let result = f(&mut item);
+        self.items.lock().unwrap().push(item);
+
+        result
+    }
+}
+
+impl<T> Default for Pool<T> {
+    fn default() -> Self {
+        Pool {
+            items: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Barrier;
+    use std::thread;
+
+    #[test]
+    fn pool_hands_out_one_item_per_caller() {
+        let pool = Pool::default();
+        let barrier = Barrier::new(2);
+
+        // Both threads hold an item at the same time, so there must be two
+        thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    pool.with(
+                        || Ok(Vec::new()),
+                        |item: &mut Vec<u8>| {
+                            barrier.wait();
+                            item.push(1);
+                            Ok(())
+                        },
+                    )
+                    .unwrap()
+                });
+            }
+        });
+        assert_eq!(2, pool.items.lock().unwrap().len());
+
+        // Items are reused afterwards
+        pool.with(
+            || panic!("pool is empty"),
+            |item| {
+                item.push(2);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(2, pool.items.lock().unwrap().len());
+    }
+}
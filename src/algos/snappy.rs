@@ -0,0 +1,82 @@
+use snap::raw::{decompress_len, max_compress_len, Decoder, Encoder};
+
+use super::pool::Pool;
+use super::{CodecId, CompressionAlgo, MAX_DECOMPRESSED_SIZE};
+use crate::error::{Error, Result};
+
+/// Snappy in its raw format, without the framing of the stream format. Has no settings, but
+/// reuses its encoders and their hash tables.
+#[derive(Default)]
+pub struct Snappy {
+    encoders: Pool<Encoder>,
+}
+
+impl Snappy {
+    pub fn new() -> Self {
+        Snappy::default()
+    }
+}
+
+impl CompressionAlgo for Snappy {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        self.encoders.with(
+            || Ok(Encoder::new()),
+            |encoder| {
+                let start = buf.len();
+                buf.resize(start + max_compress_len(bytes.len()), 0);
+                let size = encoder
+                    .compress(bytes, &mut buf[start..])
+                    .map_err(|e| Error::Io(e.into()))?;
+                buf.truncate(start + size);
+
+                Ok(size)
+            },
+        )
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let size = decompress_len(bytes).map_err(|e| Error::Io(e.into()))?;
+        if size as u64 > MAX_DECOMPRESSED_SIZE {
+            return Err(Error::OutputOverflow);
+        }
+
+        let start = buf.len();
+        buf.resize(start + size, 0);
+        Decoder::new()
+            .decompress(bytes, &mut buf[start..])
+            .map_err(|e| Error::Io(e.into()))
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::Snappy]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::get_example_trytes;
+    use super::*;
+    use crate::model::transaction::*;
+
+    #[test]
+    fn snappy_compression_works() {
+        let snappy = Snappy::new();
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = snappy
+            .compress(&bytes)
+            .expect("error compressing transaction bytes");
+
+        let decompressed = snappy
+            .decompress(&compressed)
+            .expect("error decompressing transactions bytes");
+
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+        assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+        assert!(snappy
+            .decompress(&compressed[..compressed.len() / 2])
+            .is_err());
+    }
+}
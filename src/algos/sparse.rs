@@ -0,0 +1,142 @@
+use super::varint;
+use super::{CodecId, CompressionAlgo};
+use crate::constants::*;
+use crate::error::{Error, Result};
+
+/// Replaces the delimiters of `TrimAll` with a 15-bit presence header. Empty fields cost nothing,
+/// all others are right-trimmed and prefixed with their varint encoded length.
+pub struct Sparse;
+
+const HEADER_SIZE: usize = 2;
+
+impl CompressionAlgo for Sparse {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() != PACKET_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+
+        let start = buf.len();
+        buf.resize(start + HEADER_SIZE, 0);
+        let mut presence = 0u16;
+
+        for (i, field) in FIELDS.iter().enumerate() {
+            let bytes = &bytes[field.4..field.4 + field.5];
+
+            let length = match bytes.iter().rposition(|&b| b != 0) {
+                Some(last) => last + 1,
+                None => continue,
+            };
+
+            presence |= 1 << i;
+            varint::write(length as u64, buf);
+            buf.extend_from_slice(&bytes[..length]);
+        }
+
+        buf[start..start + HEADER_SIZE].copy_from_slice(&presence.to_le_bytes());
+
+        Ok(buf.len() - start)
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+
+        let presence = u16::from_le_bytes([bytes[0], bytes[1]]);
+        if presence >> FIELDS.len() != 0 {
+            return Err(Error::OutputOverflow);
+        }
+
+        let start = buf.len();
+        buf.resize(start + PACKET_SIZE, 0);
+        let decompressed = &mut buf[start..];
+        let mut src = HEADER_SIZE;
+
+        for (i, field) in FIELDS.iter().enumerate() {
+            if presence & (1 << i) == 0 {
+                continue;
+            }
+
+            let (length, num_bytes) = varint::read(&bytes[src..])?;
+            src += num_bytes;
+
+            let length = length as usize;
+            if length > field.5 {
+                return Err(Error::OutputOverflow);
+            }
+            if bytes.len() < src + length {
+                return Err(Error::TruncatedInput);
+            }
+
+            decompressed[field.4..field.4 + length].copy_from_slice(&bytes[src..src + length]);
+            src += length;
+        }
+
+        if src != bytes.len() {
+            return Err(Error::OutputOverflow);
+        }
+
+        Ok(PACKET_SIZE)
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::Sparse]
+    }
+
+    fn per_transaction(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::get_example_trytes;
+    use super::super::TrimAll;
+    use super::*;
+    use crate::model::transaction::*;
+
+    #[test]
+    fn sparse_compression_works() {
+        let sparse = Sparse;
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = sparse
+            .compress(&bytes)
+            .expect("error compressing transaction bytes");
+
+        let decompressed = sparse
+            .decompress(&compressed)
+            .expect("error decompressing transactions bytes");
+
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+        assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+    }
+
+    #[test]
+    fn sparse_beats_trim_all_on_empty_transactions() {
+        let tx = Transaction::default().message("Hello").unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = Sparse.compress(&bytes).unwrap();
+        let trimmed = TrimAll::new().compress(&bytes).unwrap();
+
+        assert!(compressed.len() < trimmed.len());
+        assert_eq!(&bytes[..], &Sparse.decompress(&compressed).unwrap()[..]);
+    }
+
+    #[test]
+    fn sparse_rejects_malformed_input() {
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let compressed = Sparse.compress(&tx.as_bytes()).unwrap();
+
+        assert!(Sparse.compress(&[0; 100]).is_err());
+        assert!(Sparse.decompress(&[]).is_err());
+        assert!(Sparse.decompress(&[0, 0x80]).is_err());
+        assert!(Sparse
+            .decompress(&compressed[..compressed.len() - 1])
+            .is_err());
+        assert!(Sparse.decompress(&[1, 0, 0xFF, 0x7F]).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use super::varint;
+use super::{CodecId, CompressionAlgo};
+use crate::constants::*;
+use crate::convert::{bytes, number, trytes};
+use crate::error::{Error, Result};
+
+/// Largest absolute value of a 9 tryte timestamp, (3^27 - 1) / 2.
+const MAX_TIMESTAMP_ABS: i64 = 3_812_798_742_493;
+
+/// These are stored relative to the issuance timestamp.
+const DELTA_FIELDS: [Field; 5] = [
+    TIMELOCK_LOWER_BOUND,
+    TIMELOCK_UPPER_BOUND,
+    ATTACHMENT_TIMESTAMP,
+    ATTACHMENT_TIMESTAMP_LOWER_BOUND,
+    ATTACHMENT_TIMESTAMP_UPPER_BOUND,
+];
+
+/// Stores the timelock and attachment timestamp fields as varint encoded differences to the
+/// issuance timestamp. A field equal to the issuance timestamp or zero takes a single byte
+/// instead of six. All other fields are copied unchanged.
+///
+/// Each delta field holds 0 for a literal zero, otherwise the zigzag encoded difference plus 1.
+pub struct TimestampDelta;
+
+impl CompressionAlgo for TimestampDelta {
+    fn compress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        if bytes.len() != PACKET_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+        if !bytes::is_valid_2enc9(bytes) {
+            return Err(Error::InvalidTryte);
+        }
+
+        let issuance = read_timestamp(bytes, ISSUANCE_TIMESTAMP.4);
+        let start = buf.len();
+
+        for field in FIELDS.iter() {
+            if !DELTA_FIELDS.contains(field) {
+                buf.extend_from_slice(&bytes[field.4..field.4 + field.5]);
+                continue;
+            }
+
+            let timestamp = read_timestamp(bytes, field.4);
+            let encoded = if timestamp == 0 {
+                0
+            } else {
+                zigzag(timestamp - issuance) + 1
+            };
+            varint::write(encoded, buf);
+        }
+
+        Ok(buf.len() - start)
+    }
+
+    fn decompress_into(&self, bytes: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+        let start = buf.len();
+        buf.resize(start + PACKET_SIZE, 0);
+        let decompressed = &mut buf[start..];
+        let mut issuance = 0;
+        let mut src = 0;
+
+        for field in FIELDS.iter() {
+            if !DELTA_FIELDS.contains(field) {
+                if bytes.len() < src + field.5 {
+                    return Err(Error::TruncatedInput);
+                }
+
+                decompressed[field.4..field.4 + field.5]
+                    .copy_from_slice(&bytes[src..src + field.5]);
+                src += field.5;
+
+                if *field == ISSUANCE_TIMESTAMP {
+                    if !bytes::is_valid_2enc9(&decompressed[field.4..field.4 + field.5]) {
+                        return Err(Error::InvalidTryte);
+                    }
+                    issuance = read_timestamp(decompressed, field.4);
+                }
+                continue;
+            }
+
+            let (encoded, num_bytes) = varint::read(&bytes[src..])?;
+            src += num_bytes;
+
+            let timestamp = if encoded == 0 {
+                0
+            } else {
+                issuance
+                    .checked_add(unzigzag(encoded - 1))
+                    .filter(|timestamp| {
+                        (-MAX_TIMESTAMP_ABS..=MAX_TIMESTAMP_ABS).contains(timestamp)
+                    })
+                    .ok_or(Error::OutputOverflow)?
+            };
+
+            let encoded_trytes = trytes::from_i64_fixed9(timestamp);
+            decompressed[field.4..field.4 + field.5]
+                .copy_from_slice(&bytes::from_9_trytes_2enc9(&encoded_trytes));
+        }
+
+        if src != bytes.len() {
+            return Err(Error::OutputOverflow);
+        }
+
+        Ok(PACKET_SIZE)
+    }
+
+    fn codec_ids(&self) -> Vec<CodecId> {
+        vec![CodecId::TimestampDelta]
+    }
+
+    fn per_transaction(&self) -> bool {
+        true
+    }
+}
+
+/// Reads the 9 tryte timestamp stored in the 6 bytes at `offset`.
+fn read_timestamp(bytes: &[u8], offset: usize) -> i64 {
+    number::i64_from_trytes_max11(&trytes::from_6_bytes_2enc9(&bytes[offset..]))
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::get_example_trytes;
+    use super::*;
+    use crate::model::transaction::*;
+
+    #[test]
+    fn timestamp_delta_compression_works() {
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        let bytes = tx.as_bytes();
+
+        let compressed = TimestampDelta
+            .compress(&bytes)
+            .expect("error compressing transaction bytes");
+
+        let decompressed = TimestampDelta
+            .decompress(&compressed)
+            .expect("error decompressing transactions bytes");
+
+        let tx2 = Transaction::from_tx_bytes(&decompressed).unwrap();
+
+        assert_eq!(tx.as_tryte_string(), tx2.as_tryte_string());
+    }
+
+    #[test]
+    fn timestamp_delta_shrinks_close_timestamps() {
+        let mut tx = Transaction::default();
+        tx.timelock_upper_bound = tx.issuance_timestamp + 60_000;
+        tx.attachment_timestamp = tx.issuance_timestamp - 5;
+        tx.attachment_timestamp_lower_bound = -MAX_TIMESTAMP_ABS;
+        tx.attachment_timestamp_upper_bound = MAX_TIMESTAMP_ABS;
+        let bytes = tx.as_bytes();
+
+        let compressed = TimestampDelta.compress(&bytes).unwrap();
+        assert!(compressed.len() < bytes.len());
+
+        let tx2 = Transaction::from_tx_bytes(&TimestampDelta.decompress(&compressed).unwrap());
+        assert_eq!(tx.as_tryte_string(), tx2.unwrap().as_tryte_string());
+
+        // Zero and equal timestamps take one byte each
+        let tx = Transaction::default();
+        let compressed = TimestampDelta.compress(&tx.as_bytes()).unwrap();
+        assert_eq!(PACKET_SIZE - 5 * 6 + 5, compressed.len());
+    }
+
+    #[test]
+    fn timestamp_delta_rejects_malformed_input() {
+        let bytes = Transaction::default().as_bytes();
+        let compressed = TimestampDelta.compress(&bytes).unwrap();
+
+        assert!(TimestampDelta.compress(&bytes[1..]).is_err());
+        assert!(TimestampDelta.decompress(&compressed[1..]).is_err());
+        assert!(TimestampDelta
+            .decompress(&compressed[..compressed.len() - 1])
+            .is_err());
+
+        let mut trailing = compressed.clone();
+        trailing.push(0);
+        assert!(TimestampDelta.decompress(&trailing).is_err());
+
+        // A delta that leaves the timestamp range
+        let mut overflow = compressed[..TIMELOCK_LOWER_BOUND.4].to_vec();
+        varint::write(u64::MAX, &mut overflow);
+        overflow.extend_from_slice(&compressed[TIMELOCK_LOWER_BOUND.4 + 1..]);
+        assert!(TimestampDelta.decompress(&overflow).is_err());
+
+        // An issuance timestamp with bytes that aren't trytes
+        let mut invalid = compressed;
+        invalid[ISSUANCE_TIMESTAMP.4] = 0xFF;
+        assert!(TimestampDelta.decompress(&invalid).is_err());
+    }
+}
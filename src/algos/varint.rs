@@ -0,0 +1,61 @@
+//! LEB128 encoding of unsigned integers: 7 bits per byte, least significant group first, and the
+//! high bit set on every byte but the last.
+
+use crate::error::{Error, Result};
+
+const MAX_VARINT_LENGTH: usize = 10;
+
+pub fn write(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Returns the decoded value and the number of bytes it occupied.
+pub fn read(bytes: &[u8]) -> Result<(u64, usize)> {
+    let mut value = 0;
+
+    for (i, &byte) in bytes.iter().enumerate().take(MAX_VARINT_LENGTH) {
+        value |= u64::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+
+    if bytes.len() < MAX_VARINT_LENGTH {
+        Err(Error::TruncatedInput)
+    } else {
+        Err(Error::OutputOverflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_read() {
+        for &value in &[0, 1, 127, 128, 1458, 300_000, u64::MAX] {
+            let mut buf = Vec::new();
+            write(value, &mut buf);
+
+            assert_eq!((value, buf.len()), read(&buf).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_small_values_take_one_byte() {
+        let mut buf = Vec::new();
+        write(127, &mut buf);
+        assert_eq!(vec![127], buf);
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        assert!(read(&[]).is_err());
+        assert!(read(&[0x80, 0x80]).is_err());
+        assert!(read(&[0xFF; 11]).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+//! A global allocator for tests that counts the allocations of the current thread, to check that
+//! the send and receive paths reuse their memory. Memory that C libraries like liblz4 or zstd
+//! allocate themselves isn't counted.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    /// Allocations of this thread since counting started, `None` while not counting.
+    static COUNT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Runs `f` and returns how often it allocated or grew memory on this thread.
+pub fn count(f: impl FnOnce()) -> usize {
+    COUNT.with(|count| count.set(Some(0)));
+    f();
+    COUNT
+        .with(|count| count.take())
+        .expect("counting was started")
+}
+
+fn record() {
+    // The thread local is gone while the thread shuts down
+    let _ = COUNT.try_with(|count| {
+        if let Some(num) = count.get() {
+            count.set(Some(num + 1));
+        }
+    });
+}
+
+// SAFETY: Every call is passed on to the system allocator unchanged.
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_counted() {
+        assert_eq!(0, count(|| {}));
+        assert_eq!(1, count(|| drop(Box::new(1))));
+
+        let mut buf = Vec::with_capacity(8);
+        assert_eq!(0, count(|| buf.extend_from_slice(&[1; 8])));
+        assert_eq!(1, count(|| buf.push(9)));
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::algos::{CompressionAlgo, Registry, TryteModel};
+use crate::corpus;
+use crate::stats::{micros, Summary};
+
+/// What one codec achieved on the whole corpus.
+struct Measurement {
+    name: String,
+    uncompressed: usize,
+    compressed: usize,
+    compress: Summary,
+    decompress: Summary,
+}
+
+impl Measurement {
+    fn ratio(&self) -> f64 {
+        self.uncompressed as f64 / self.compressed as f64
+    }
+}
+
+/// Compresses and decompresses every transaction of the corpus with each codec and prints the
+/// ratio, timings and throughput. Decompression goes through a `Registry` like in the receiver.
+/// Returns `false` if there was nothing to measure.
+pub fn start(
+    corpus_path: &Path,
+    algos: Vec<(String, Box<dyn CompressionAlgo>)>,
+    dictionary: Vec<u8>,
+    model: &TryteModel,
+    rounds: usize,
+    csv_path: Option<&Path>,
+) -> bool {
+    let txs: Vec<Vec<u8>> = corpus::read(corpus_path)
+        .iter()
+        .map(|tx| tx.as_bytes().to_vec())
+        .collect();
+
+    if txs.is_empty() || rounds == 0 {
+        eprintln!(
+            "error: nothing to measure with {} transactions and {} rounds",
+            txs.len(),
+            rounds
+        );
+        return false;
+    }
+
+    println!(
+        "Measuring {} algorithms on {} transactions, {} rounds each.",
+        algos.len(),
+        txs.len(),
+        rounds,
+    );
+
+    // Print every row as soon as it is measured, high levels take a while
+    print_header();
+    let measurements: Vec<Measurement> = algos
+        .into_iter()
+        .filter_map(|(name, algo)| {
+            // A fresh registry, so stateful codecs start out in lockstep
+            let registry = Registry::new(dictionary.clone(), model);
+            measure(name, algo.as_ref(), &registry, &txs, rounds)
+        })
+        .inspect(print_row)
+        .collect();
+
+    if let Some(csv_path) = csv_path {
+        fs::write(csv_path, to_csv(&measurements)).expect("Couldn't write CSV file");
+        println!("Wrote results to {}.", csv_path.display());
+    }
+
+    true
+}
+
+fn measure(
+    name: String,
+    algo: &dyn CompressionAlgo,
+    registry: &Registry,
+    txs: &[Vec<u8>],
+    rounds: usize,
+) -> Option<Measurement> {
+    let codec_ids = algo.codec_ids();
+    let mut compressed = Vec::new();
+    let mut decompressed = Vec::new();
+    let mut compress_times = Vec::with_capacity(txs.len() * rounds);
+    let mut decompress_times = Vec::with_capacity(txs.len() * rounds);
+    let mut uncompressed_size = 0;
+    let mut compressed_size = 0;
+
+    for _ in 0..rounds {
+        for tx in txs {
+            compressed.clear();
+            let start = Instant::now();
+            algo.compress_into(tx, &mut compressed)
+                .unwrap_or_else(|e| panic!("{} failed to compress: {}", name, e));
+            compress_times.push(start.elapsed());
+
+            decompressed.clear();
+            let start = Instant::now();
+            registry
+                .decompress_into(&codec_ids, &compressed, &mut decompressed)
+                .unwrap_or_else(|e| panic!("{} failed to decompress: {}", name, e));
+            decompress_times.push(start.elapsed());
+
+            assert!(
+                &decompressed == tx,
+                "{} doesn't restore the original bytes",
+                name
+            );
+
+            uncompressed_size += tx.len();
+            compressed_size += compressed.len();
+        }
+    }
+
+    Some(Measurement {
+        name,
+        uncompressed: uncompressed_size,
+        compressed: compressed_size,
+        compress: Summary::new(&mut compress_times)?,
+        decompress: Summary::new(&mut decompress_times)?,
+    })
+}
+
+/// Megabytes of uncompressed transaction bytes per second.
+fn throughput(bytes: usize, elapsed: Duration) -> f64 {
+    bytes as f64 / elapsed.as_secs_f64() / 1e6
+}
+
+fn print_header() {
+    println!(
+        "{:<18} {:>6} | {:>9} {:>9} {:>9} {:>8} | {:>9} {:>9} {:>9} {:>8}",
+        "algorithm", "ratio", "comp µs", "p50", "p99", "MB/s", "decomp µs", "p50", "p99", "MB/s",
+    );
+}
+
+fn print_row(m: &Measurement) {
+    println!(
+        "{:<18} {:>6.2} | {:>9.2} {:>9.2} {:>9.2} {:>8.1} | {:>9.2} {:>9.2} {:>9.2} {:>8.1}",
+        m.name,
+        m.ratio(),
+        micros(m.compress.mean),
+        micros(m.compress.p50),
+        micros(m.compress.p99),
+        throughput(m.uncompressed, m.compress.total),
+        micros(m.decompress.mean),
+        micros(m.decompress.p50),
+        micros(m.decompress.p99),
+        throughput(m.uncompressed, m.decompress.total),
+    );
+}
+
+/// One row per algorithm, times in nanoseconds.
+fn to_csv(measurements: &[Measurement]) -> String {
+    let mut csv = String::from(
+        "algorithm,ratio,uncompressed_bytes,compressed_bytes,\
+         compress_mean_ns,compress_p50_ns,compress_p90_ns,compress_p99_ns,compress_max_ns,compress_mb_s,\
+         decompress_mean_ns,decompress_p50_ns,decompress_p90_ns,decompress_p99_ns,decompress_max_ns,decompress_mb_s\n",
+    );
+
+    for m in measurements {
+        csv.push_str(&format!(
+            "{},{:.4},{},{},{},{:.2},{},{:.2}\n",
+            m.name,
+            m.ratio(),
+            m.uncompressed,
+            m.compressed,
+            summary_csv(&m.compress),
+            throughput(m.uncompressed, m.compress.total),
+            summary_csv(&m.decompress),
+            throughput(m.uncompressed, m.decompress.total),
+        ));
+    }
+
+    csv
+}
+
+/// Mean, p50, p90, p99 and max in nanoseconds.
+fn summary_csv(summary: &Summary) -> String {
+    format!(
+        "{},{},{},{},{}",
+        summary.mean.as_nanos(),
+        summary.p50.as_nanos(),
+        summary.p90.as_nanos(),
+        summary.p99.as_nanos(),
+        summary.max.as_nanos(),
+    )
+}
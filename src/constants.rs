@@ -2,7 +2,7 @@ use lazy_static::lazy_static;
 use regex::Regex;
 
 // Transaction (trits offset, trits length, trytes offset, trytes length, bytes offset, bytes length)
-type Field = (usize, usize, usize, usize, usize, usize);
+pub type Field = (usize, usize, usize, usize, usize, usize);
 pub const SIGNATURE_FRAGMENTS: Field = (0, 6561, 0, 2187, 0, 1458);
 pub const EXTRA_DATA_DIGEST: Field = (6561, 243, 2187, 81, 1458, 54);
 pub const ADDRESS: Field = (6804, 243, 2268, 81, 1512, 54);
@@ -19,10 +19,48 @@ pub const ATTACHMENT_TIMESTAMP_LOWER_BOUND: Field = (7884, 27, 2628, 9, 1752, 6)
 pub const ATTACHMENT_TIMESTAMP_UPPER_BOUND: Field = (7911, 27, 2637, 9, 1758, 6);
 pub const NONCE: Field = (7938, 81, 2646, 27, 1764, 18);
 
+pub const FIELDS: [Field; 15] = [
+    SIGNATURE_FRAGMENTS,
+    EXTRA_DATA_DIGEST,
+    ADDRESS,
+    VALUE,
+    ISSUANCE_TIMESTAMP,
+    TIMELOCK_LOWER_BOUND,
+    TIMELOCK_UPPER_BOUND,
+    BUNDLE_NONCE,
+    TRUNK_HASH,
+    BRANCH_HASH,
+    TAG,
+    ATTACHMENT_TIMESTAMP,
+    ATTACHMENT_TIMESTAMP_LOWER_BOUND,
+    ATTACHMENT_TIMESTAMP_UPPER_BOUND,
+    NONCE,
+];
+
+/// Names of the transaction fields, in the order of `FIELDS`.
+pub const FIELD_NAMES: [&str; 15] = [
+    "signature_fragments",
+    "extra_data_digest",
+    "address",
+    "value",
+    "issuance_timestamp",
+    "timelock_lower_bound",
+    "timelock_upper_bound",
+    "bundle_nonce",
+    "trunk",
+    "branch",
+    "tag",
+    "attachment_timestamp",
+    "attachment_timestamp_lower_bound",
+    "attachment_timestamp_upper_bound",
+    "nonce",
+];
+
 pub const TRANSACTION_SIZE_TRITS: usize = 8019;
 pub const TRANSACTION_SIZE_TRYTES: usize = TRANSACTION_SIZE_TRITS / 3; // =2673
 pub const TRANSACTION_SIZE_BYTES: usize = TRANSACTION_SIZE_TRITS / 9 * 2; // =1782
 pub const PACKET_SIZE: usize = TRANSACTION_SIZE_BYTES;
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
 
 pub const MIN_MESSAGE_LENGTH: usize = 10;
 pub const MAX_MESSAGE_LENGTH: usize = 1458;
@@ -59,6 +97,16 @@ mod tests {
         assert_eq!(sum, TRANSACTION_SIZE_TRITS);
     }
 
+    #[test]
+    fn test_transaction_fields_are_ordered() {
+        let mut offset = 0;
+        for field in FIELDS.iter() {
+            assert_eq!(offset, field.4);
+            offset += field.5;
+        }
+        assert_eq!(offset, TRANSACTION_SIZE_BYTES);
+    }
+
     #[test]
     fn test_transaction_trit_offset_constants() {
         assert_eq!(
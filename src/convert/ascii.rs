@@ -22,13 +22,12 @@ pub fn from_tryte_string(tryte_string: &str) -> String {
         ascii_chars[i * 2 + 1] = (index % 127) as u8;
     }
 
-    if ascii_chars[ascii_chars.len() - 1] == 0 {
-        ascii_chars.remove(ascii_chars.len() - 1);
+    if ascii_chars.last() == Some(&0) {
+        ascii_chars.pop();
     }
 
-    // NOTE: make sure that ASCIIs are always valid
-    String::from_utf8(ascii_chars).expect("couldn't create utf8 string")
-    //String::from_utf8_lossy(&ascii_chars[..]).to_string()
+    // NOTE: trytes received from the network can encode anything, so don't trust them to be ASCII
+    String::from_utf8_lossy(&ascii_chars[..]).to_string()
 }
 
 #[cfg(test)]
@@ -45,6 +44,12 @@ mod tests {
         assert_eq!("Hello", ascii_text);
     }
 
+    #[test]
+    fn test_from_tryte_string_with_garbage() {
+        assert_eq!("", from_tryte_string("999999"));
+        assert!(!from_tryte_string("ZZZZZZ").is_empty());
+    }
+
     #[test]
     fn test_encode_decode_ascii() {
         assert_eq!(
@@ -11,10 +11,8 @@ const NINE: u8 = TRYTE_TO_ASCII[0];
 const A: u8 = TRYTE_TO_ASCII[1];
 const TRANSACTION_SIZE_TRITS_DIV_9: usize = TRANSACTION_SIZE_TRITS / 9;
 
-pub fn from_trytes_2enc9(trytes: &[Tryte]) -> Vec<Byte> {
-    assert_eq!(0, trytes.len() % 3);
-
-    let mut bytes = vec![0u8; trytes.len() / 3 * 2];
+pub fn from_tx_trytes_2enc9(trytes: &TxTrytes) -> TxBytes {
+    let mut bytes = [0u8; TRANSACTION_SIZE_BYTES];
 
     for i in 0..trytes.len() / 3 {
         let t0 = trytes[3 * i];
@@ -31,10 +29,10 @@ pub fn from_trytes_2enc9(trytes: &[Tryte]) -> Vec<Byte> {
     bytes
 }
 
-pub fn from_tx_trytes_2enc9(trytes: &TxTrytes) -> TxBytes {
-    let mut bytes = [0u8; TRANSACTION_SIZE_BYTES];
+pub fn from_81_trytes_2enc9(trytes: &[Tryte]) -> Bytes54 {
+    let mut bytes = [0u8; 54];
 
-    for i in 0..trytes.len() / 3 {
+    for i in 0..27 {
         let t0 = trytes[3 * i];
         let t1 = trytes[3 * i + 1];
         let t2 = trytes[3 * i + 2];
@@ -49,10 +47,10 @@ pub fn from_tx_trytes_2enc9(trytes: &TxTrytes) -> TxBytes {
     bytes
 }
 
-pub fn from_81_trytes_2enc9(trytes: &[Tryte]) -> Bytes54 {
-    let mut bytes = [0u8; 54];
+pub fn from_9_trytes_2enc9(trytes: &[Tryte]) -> [Byte; 6] {
+    let mut bytes = [0u8; 6];
 
-    for i in 0..27 {
+    for i in 0..3 {
         let t0 = trytes[3 * i];
         let t1 = trytes[3 * i + 1];
         let t2 = trytes[3 * i + 2];
@@ -67,6 +65,16 @@ pub fn from_81_trytes_2enc9(trytes: &[Tryte]) -> Bytes54 {
     bytes
 }
 
+/// Checks that every pair of bytes encodes three valid trytes.
+pub fn is_valid_2enc9(bytes: &[u8]) -> bool {
+    let mut pairs = bytes.chunks_exact(2);
+
+    pairs.all(|pair| {
+        let (b0, b1) = (pair[0] as usize, pair[1] as usize);
+        b0 / 8 < 27 && b1 / 8 < 27 && b0 % 8 + 8 * (b1 % 8) < 27
+    }) && pairs.remainder().is_empty()
+}
+
 pub fn from_tx_trits_2enc9(trits: &TxTrits) -> TxBytes {
     let mut bytes = [0u8; TRANSACTION_SIZE_BYTES];
 
@@ -79,8 +87,10 @@ pub fn from_tx_trits_2enc9(trits: &TxTrits) -> TxBytes {
         i1 = if i1 < 0 { i1 + 27 } else { i1 };
         i2 = if i2 < 0 { i2 + 27 } else { i2 };
 
-        bytes[2 * i] = (i0 * 8 + i2 % 8) as u8;
-        bytes[2 * i + 1] = (i1 * 8 + i2 / 8) as u8;
+        let (i0, i1, i2) = (i0 as u8, i1 as u8, i2 as u8);
+
+        bytes[2 * i] = i0 * 8 + i2 % 8;
+        bytes[2 * i + 1] = i1 * 8 + i2 / 8;
     }
     bytes
 }
@@ -97,8 +107,10 @@ pub fn from_243_trits_2enc9(trits: &[i8; 243]) -> [u8; 54] {
         i1 = if i1 < 0 { i1 + 27 } else { i1 };
         i2 = if i2 < 0 { i2 + 27 } else { i2 };
 
-        bytes[2 * i] = (i0 * 8 + i2 % 8) as u8;
-        bytes[2 * i + 1] = (i1 * 8 + i2 / 8) as u8;
+        let (i0, i1, i2) = (i0 as u8, i1 as u8, i2 as u8);
+
+        bytes[2 * i] = i0 * 8 + i2 % 8;
+        bytes[2 * i + 1] = i1 * 8 + i2 / 8;
     }
     bytes
 }
@@ -137,4 +149,16 @@ mod tests {
             .unwrap()
         );
     }
+
+    #[test]
+    fn test_from_tx_trits() {
+        let example_tryte_string = get_example_trytes();
+        let mut tx_trytes = [0; TRANSACTION_SIZE_TRYTES];
+        tx_trytes[..].copy_from_slice(example_tryte_string.as_bytes());
+
+        let bytes = from_tx_trytes_2enc9(&tx_trytes);
+        let trits = super::super::trits::from_tx_bytes_2enc9(&bytes);
+
+        assert_eq!(&bytes[..], &from_tx_trits_2enc9(&trits)[..]);
+    }
 }
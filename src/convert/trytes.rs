@@ -37,27 +37,9 @@ macro_rules! from_bytes_2enc9_fixed_size {
 
 from_bytes_2enc9_fixed_size!(from_54_bytes_2enc9, 81);
 from_bytes_2enc9_fixed_size!(from_18_bytes_2enc9, 27);
+from_bytes_2enc9_fixed_size!(from_6_bytes_2enc9, 9);
 from_bytes_2enc9_fixed_size!(from_tx_bytes_2enc9, TRANSACTION_SIZE_TRYTES);
 
-pub fn from_bytes_2enc9(bytes: &[u8], offset: usize, len: usize) -> Vec<Tryte> {
-    assert!(len % 2 == 0);
-
-    let mut trytes = vec![TRYTE_TO_ASCII[0]; len / 2 * 3];
-
-    for i in 0..(trytes.len() / 3) {
-        let pos = offset + 2 * i;
-
-        let b0 = bytes[pos] as usize;
-        let b1 = bytes[pos + 1] as usize;
-
-        trytes[3 * i] = TRYTE_TO_ASCII[b0 / 8];
-        trytes[3 * i + 1] = TRYTE_TO_ASCII[b1 / 8];
-        trytes[3 * i + 2] = TRYTE_TO_ASCII[b0 % 8 + 8 * (b1 % 8)];
-    }
-
-    trytes
-}
-
 pub fn from_trits(trits: &[Trit]) -> Vec<Tryte> {
     assert!(trits.len() % 3 == 0);
     let mut trytes = vec![TRYTE_TO_ASCII[0]; trits.len() / 3];
@@ -0,0 +1,22 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::model::transaction::*;
+
+/// Reads a corpus file containing one transaction tryte string per line. Empty lines are skipped.
+pub fn read(path: &Path) -> Vec<Transaction> {
+    let file = File::open(path).expect("Couldn't open corpus file");
+
+    BufReader::new(file)
+        .lines()
+        .map(|line| line.expect("Couldn't read line from corpus file"))
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            Transaction::from_tryte_string(line.trim()).unwrap_or_else(|e| {
+                panic!("Invalid transaction in line {} of corpus: {}", i + 1, e)
+            })
+        })
+        .collect()
+}
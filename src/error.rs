@@ -0,0 +1,68 @@
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors that occur while compressing, decompressing or parsing transactions. Malformed input
+/// must always end up here instead of panicking, since it can come from any UDP packet.
+#[derive(Debug)]
+pub enum Error {
+    /// The input ended before all expected bytes were read.
+    TruncatedInput,
+    /// A codec id that is unknown or that has no registered codec.
+    UnknownCodec(u8),
+    /// A field delimiter is missing or in the wrong place.
+    BadDelimiter,
+    /// A reference to state the decoder doesn't have, e.g. an unknown hash cache index.
+    UnknownReference(u8),
+    /// The decoded data doesn't fit into its destination.
+    OutputOverflow,
+    /// A character or byte that doesn't represent a tryte.
+    InvalidTryte,
+    /// A datagram that doesn't start with the magic bytes.
+    BadMagic,
+    /// A datagram with a protocol version we don't understand.
+    UnsupportedVersion(u8),
+    /// Codecs that can't be chained in the given order, e.g. to re-encode with them.
+    InvalidPipeline(String),
+    /// A duplicate or late datagram for a stateful codec, which must see every datagram once and
+    /// in order.
+    OutOfSequence,
+    /// An error reported by an underlying compression library.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::TruncatedInput => write!(f, "truncated input"),
+            Error::UnknownCodec(id) => write!(f, "unknown codec id {}", id),
+            Error::BadDelimiter => write!(f, "missing or misplaced delimiter"),
+            Error::UnknownReference(index) => write!(f, "unknown reference {}", index),
+            Error::OutputOverflow => write!(f, "output overflow"),
+            Error::InvalidTryte => write!(f, "invalid tryte"),
+            Error::BadMagic => write!(f, "bad magic bytes"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported protocol version {}", version)
+            }
+            Error::InvalidPipeline(reason) => write!(f, "invalid pipeline: {}", reason),
+            Error::OutOfSequence => write!(f, "duplicate or late packet for a stateful codec"),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
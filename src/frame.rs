@@ -0,0 +1,265 @@
+use std::convert::TryInto;
+
+use crate::algos::varint;
+use crate::algos::CodecId;
+use crate::error::{Error, Result};
+
+/// Every datagram starts with these bytes ("IX").
+pub const MAGIC: [u8; 2] = [0x49, 0x58];
+pub const VERSION: u8 = 5;
+
+/// Set if the payload holds a batch of transactions.
+const FLAG_BATCH: u8 = 0x01;
+
+/// Bytes of the header before the codec ids: magic, version, flags, session, sequence number, send
+/// time and compression time.
+const FIXED_SIZE: usize = 2 + 1 + 1 + 4 + 4 + 8 + 4;
+
+/// Datagram header: magic (2 bytes), version (1 byte), flags (1 byte), session (4 bytes), sequence
+/// number (4 bytes), send time (8 bytes), compression time (4 bytes), number of codecs (1 byte)
+/// followed by one byte per codec id in the order they were applied by the sender. Integers are
+/// big endian.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Header {
+    pub codec_ids: Vec<CodecId>,
+    /// Whether the payload is a batch of records (see `write_record`) instead of one transaction.
+    pub batch: bool,
+    /// Picked at random by every run of a sender, so a receiver notices restarts.
+    pub session: u32,
+    /// Counts the datagrams of a sender, wrapping around after `u32::MAX`.
+    pub seq: u32,
+    /// When the datagram was sent, from `time::get_monotonic_time_nanos`.
+    pub sent_at: u64,
+    /// Nanoseconds the sender spent compressing the payload, saturating at `u32::MAX`.
+    pub compress_ns: u32,
+}
+
+impl Header {
+    pub fn new(codec_ids: Vec<CodecId>, batch: bool) -> Self {
+        assert!(codec_ids.len() <= u8::MAX as usize);
+
+        Header {
+            codec_ids,
+            batch,
+            session: rand::random(),
+            seq: 0,
+            sent_at: 0,
+            compress_ns: 0,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        FIXED_SIZE + 1 + self.codec_ids.len()
+    }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.resize(start + self.size(), 0);
+        self.rewrite(&mut buf[start..]);
+    }
+
+    /// Overwrites the header at the start of a datagram that already holds one of the same size,
+    /// e.g. to set the send and compression time after the payload was compressed.
+    pub fn rewrite(&self, datagram: &mut [u8]) {
+        let (fixed, codecs) = datagram[..self.size()].split_at_mut(FIXED_SIZE);
+
+        fixed[..2].copy_from_slice(&MAGIC);
+        fixed[2] = VERSION;
+        fixed[3] = if self.batch { FLAG_BATCH } else { 0 };
+        fixed[4..8].copy_from_slice(&self.session.to_be_bytes());
+        fixed[8..12].copy_from_slice(&self.seq.to_be_bytes());
+        fixed[12..20].copy_from_slice(&self.sent_at.to_be_bytes());
+        fixed[20..].copy_from_slice(&self.compress_ns.to_be_bytes());
+
+        codecs[0] = self.codec_ids.len() as u8;
+        for (byte, &id) in codecs[1..].iter_mut().zip(&self.codec_ids) {
+            *byte = id as u8;
+        }
+    }
+
+    /// Parses the header into this one, reusing its codec ids, and returns the remaining payload.
+    /// On error the header is unspecified.
+    pub fn read_into<'a>(&mut self, datagram: &'a [u8]) -> Result<&'a [u8]> {
+        if datagram.len() < FIXED_SIZE {
+            return Err(Error::TruncatedInput);
+        }
+        if datagram[..MAGIC.len()] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+
+        let version = datagram[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let flags = datagram[MAGIC.len() + 1];
+        let (session, rest) = datagram[MAGIC.len() + 2..FIXED_SIZE].split_at(4);
+        let (seq, times) = rest.split_at(4);
+        let (sent_at, compress_ns) = times.split_at(8);
+        let payload = read_codec_ids_into(&datagram[FIXED_SIZE..], &mut self.codec_ids)?;
+
+        self.batch = flags & FLAG_BATCH != 0;
+        self.session = u32::from_be_bytes(session.try_into().unwrap());
+        self.seq = u32::from_be_bytes(seq.try_into().unwrap());
+        self.sent_at = u64::from_be_bytes(sent_at.try_into().unwrap());
+        self.compress_ns = u32::from_be_bytes(compress_ns.try_into().unwrap());
+
+        Ok(payload)
+    }
+}
+
+/// Appends the number of codecs (1 byte) followed by one byte per codec id.
+pub fn write_codec_ids(codec_ids: &[CodecId], buf: &mut Vec<u8>) {
+    buf.push(codec_ids.len() as u8);
+    buf.extend(codec_ids.iter().map(|&id| id as u8));
+}
+
+/// Parses codec ids written by `write_codec_ids` into `codec_ids`, replacing what it held, and
+/// returns the remaining bytes.
+pub fn read_codec_ids_into<'a>(bytes: &'a [u8], codec_ids: &mut Vec<CodecId>) -> Result<&'a [u8]> {
+    let (&num_codecs, bytes) = bytes.split_first().ok_or(Error::TruncatedInput)?;
+    let num_codecs = num_codecs as usize;
+    if bytes.len() < num_codecs {
+        return Err(Error::TruncatedInput);
+    }
+
+    codec_ids.clear();
+    for &byte in &bytes[..num_codecs] {
+        let id = CodecId::from_byte(byte).ok_or(Error::UnknownCodec(byte))?;
+        codec_ids.push(id);
+    }
+
+    Ok(&bytes[num_codecs..])
+}
+
+/// Appends one transaction of a batch, prefixed with its varint encoded length.
+pub fn write_record(record: &[u8], buf: &mut Vec<u8>) {
+    varint::write(record.len() as u64, buf);
+    buf.extend_from_slice(record);
+}
+
+/// Splits a batch into its records. A batch holds at least one record.
+pub fn records(bytes: &[u8]) -> Result<Records<'_>> {
+    if bytes.is_empty() {
+        return Err(Error::TruncatedInput);
+    }
+
+    Ok(Records { bytes })
+}
+
+/// Iterator over the records of a batch, see `records`. Ends after the first error.
+pub struct Records<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+
+        let (length, num_bytes) = match varint::read(self.bytes) {
+            Ok(read) => read,
+            Err(e) => {
+                self.bytes = &[];
+                return Some(Err(e));
+            }
+        };
+        let bytes = &self.bytes[num_bytes..];
+
+        let length = length as usize;
+        if bytes.len() < length {
+            self.bytes = &[];
+            return Some(Err(Error::TruncatedInput));
+        }
+
+        let (record, rest) = bytes.split_at(length);
+        self.bytes = rest;
+        Some(Ok(record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_read_header() {
+        for &batch in &[false, true] {
+            let mut header = Header::new(vec![CodecId::TrimAll, CodecId::Lz4], batch);
+            header.session = 0x0BAD_CAFE;
+            header.seq = 0xDEAD_BEEF;
+            header.sent_at = 1_561_000_000_123_456_789;
+            header.compress_ns = 12_345;
+
+            let mut datagram = Vec::new();
+            header.write(&mut datagram);
+            datagram.extend_from_slice(b"payload");
+
+            assert_eq!(header.size() + 7, datagram.len());
+
+            let mut header2 = Header::default();
+            let payload = header2.read_into(&datagram).unwrap();
+            assert_eq!(header, header2);
+            assert_eq!(b"payload", payload);
+        }
+    }
+
+    #[test]
+    fn test_rewrite_header() {
+        let mut header = Header::new(vec![CodecId::Lz4], false);
+
+        let mut datagram = Vec::new();
+        header.write(&mut datagram);
+        datagram.extend_from_slice(b"payload");
+
+        header.seq = 7;
+        header.sent_at = 42;
+        header.compress_ns = 3;
+        header.rewrite(&mut datagram);
+
+        let mut header2 = Header::default();
+        let payload = header2.read_into(&datagram).unwrap();
+        assert_eq!(header, header2);
+        assert_eq!(b"payload", payload);
+    }
+
+    #[test]
+    fn test_read_header_rejects_garbage() {
+        let read = |datagram: &[u8]| Header::default().read_into(datagram).map(|_| ());
+        let with = |prefix: &[u8], codecs: &[u8]| {
+            let mut datagram = prefix.to_vec();
+            datagram.extend_from_slice(&[0; 20]);
+            datagram.extend_from_slice(codecs);
+            datagram
+        };
+
+        assert!(read(&[]).is_err());
+        assert!(read(&[0x49, 0x58, VERSION, 0]).is_err());
+        assert!(read(&with(&[0x49, 0x59, VERSION, 0], &[0])).is_err());
+        assert!(read(&with(&[0x49, 0x58, VERSION + 1, 0], &[0])).is_err());
+        assert!(read(&with(&[0x49, 0x58, VERSION, 0], &[2, 1])).is_err());
+        assert!(read(&with(&[0x49, 0x58, VERSION, 0], &[1, 0xEE])).is_err());
+        assert!(read(&with(&[0x49, 0x58, VERSION, 0], &[1, 1])).is_ok());
+    }
+
+    #[test]
+    fn test_write_read_records() {
+        let mut batch = Vec::new();
+        write_record(b"first", &mut batch);
+        write_record(b"", &mut batch);
+        write_record(&[7; 300], &mut batch);
+
+        fn read_records(bytes: &[u8]) -> Result<Vec<&[u8]>> {
+            records(bytes)?.collect()
+        }
+
+        let records = read_records(&batch).unwrap();
+        assert_eq!(vec![&b"first"[..], &b""[..], &[7; 300][..]], records);
+
+        assert!(read_records(&batch[..batch.len() - 1]).is_err());
+        assert!(read_records(&[]).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hasher;
+use std::net::{SocketAddr, UdpSocket};
+use std::slice;
+
+use crate::constants::{MAX_DATAGRAM_SIZE, PACKET_SIZE};
+use crate::error::Result;
+use crate::frame::Header;
+use crate::model::transaction::*;
+use crate::receiver::Reencode;
+
+/// Transactions remembered to suppress duplicates, about 2 MB of hashes.
+const SEEN_CAPACITY: usize = 100_000;
+
+/// Remembers the hashes of the most recent transactions and forgets the oldest first.
+pub struct SeenCache {
+    capacity: usize,
+    hashes: HashSet<u64>,
+    order: VecDeque<u64>,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize) -> Self {
+        SeenCache {
+            capacity,
+            hashes: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Remembers the bytes and returns whether they weren't seen before.
+    pub fn insert(&mut self, bytes: &[u8]) -> bool {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(bytes);
+        let hash = hasher.finish();
+
+        if !self.hashes.insert(hash) {
+            return false;
+        }
+
+        self.order.push_back(hash);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.hashes.remove(&oldest);
+            }
+        }
+
+        true
+    }
+}
+
+/// A node the receiver forwards new transactions to, one per datagram.
+struct Neighbor {
+    addr: SocketAddr,
+    codec: Reencode,
+    /// Session of all datagrams to this neighbour.
+    session: u32,
+    /// Sequence number of the next datagram to this neighbour.
+    seq: u32,
+    packets: u64,
+    bytes: u64,
+}
+
+impl Neighbor {
+    fn forward(
+        &mut self,
+        socket: &UdpSocket,
+        header: &Header,
+        tx: &Transaction,
+        datagram: &mut Vec<u8>,
+    ) -> Result<()> {
+        // Mirrored codecs are those of the incoming datagram, but never batched
+        let mut forwarded = Header::new(header.codec_ids.clone(), false);
+        forwarded.session = self.session;
+        forwarded.seq = self.seq;
+        self.codec
+            .encode(self.addr, &forwarded, slice::from_ref(tx), datagram)?;
+        socket.send_to(datagram, self.addr)?;
+
+        self.seq = self.seq.wrapping_add(1);
+        self.packets += 1;
+        self.bytes += datagram.len() as u64;
+
+        Ok(())
+    }
+}
+
+/// Relays transactions like an Ict node: every transaction not seen before is forwarded to all
+/// neighbours except the one it came from, re-encoded with each neighbour's codec.
+pub struct Gossip {
+    neighbors: Vec<Neighbor>,
+    seen: SeenCache,
+    new: u64,
+    duplicates: u64,
+    datagram: Vec<u8>,
+}
+
+impl Gossip {
+    pub fn new(neighbors: Vec<(SocketAddr, Reencode)>) -> Self {
+        Gossip {
+            neighbors: neighbors
+                .into_iter()
+                .map(|(addr, codec)| Neighbor {
+                    addr,
+                    codec,
+                    session: rand::random(),
+                    seq: 0,
+                    packets: 0,
+                    bytes: 0,
+                })
+                .collect(),
+            seen: SeenCache::new(SEEN_CAPACITY),
+            new: 0,
+            duplicates: 0,
+            datagram: Vec::with_capacity(MAX_DATAGRAM_SIZE),
+        }
+    }
+
+    /// Forwards the transactions of a datagram that weren't seen before. Returns how many of them
+    /// were new.
+    pub fn relay(
+        &mut self,
+        socket: &UdpSocket,
+        src_addr: SocketAddr,
+        header: &Header,
+        txs: &[Transaction],
+    ) -> usize {
+        let mut num_new = 0;
+
+        for tx in txs {
+            if !self.seen.insert(&tx.as_bytes()[..]) {
+                self.duplicates += 1;
+                continue;
+            }
+            num_new += 1;
+
+            for neighbor in self.neighbors.iter_mut() {
+                if neighbor.addr == src_addr {
+                    continue;
+                }
+                if let Err(e) = neighbor.forward(socket, header, tx, &mut self.datagram) {
+                    println!("Couldn't relay to {}: {}", neighbor.addr, e);
+                }
+            }
+        }
+
+        self.new += num_new as u64;
+        num_new
+    }
+
+    /// Prints how many transactions were relayed and how many bytes each neighbour got.
+    pub fn print(&self) {
+        println!(
+            "Relayed {} new transactions, suppressed {} already seen",
+            self.new, self.duplicates
+        );
+
+        for neighbor in &self.neighbors {
+            // Every datagram to a neighbour carries one transaction
+            let uncompressed = neighbor.packets * PACKET_SIZE as u64;
+            println!(
+                "    to {}: {} packets with {} bytes instead of {} ({:.2})",
+                neighbor.addr,
+                neighbor.packets,
+                neighbor.bytes,
+                uncompressed,
+                uncompressed as f64 / neighbor.bytes.max(1) as f64,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::algos::*;
+    use crate::receiver;
+    use crate::sender;
+
+    /// Everything that arrives at the socket until nothing more comes, decoded.
+    fn receive_all(socket: &UdpSocket) -> Vec<(Header, Vec<Transaction>)> {
+        let registry = Registry::new(Vec::new(), &TryteModel::default());
+        let mut buf = [0; MAX_DATAGRAM_SIZE];
+        let mut received = Vec::new();
+
+        while let Ok(num_bytes) = socket.recv(&mut buf) {
+            let mut decoded = receiver::Decoded::default();
+            receiver::decode(&registry, &buf[..num_bytes], 0, &mut decoded).unwrap();
+            received.push((decoded.header.clone(), decoded.txs().to_vec()));
+        }
+
+        received
+    }
+
+    #[test]
+    fn relay_forwards_new_transactions_to_the_other_neighbors() {
+        let bind = || {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket
+                .set_read_timeout(Some(Duration::from_millis(100)))
+                .unwrap();
+            socket
+        };
+        let node = bind();
+        let neighbors = [bind(), bind(), bind()];
+        let addrs: Vec<SocketAddr> = neighbors.iter().map(|n| n.local_addr().unwrap()).collect();
+
+        let mut gossip = Gossip::new(vec![
+            (addrs[0], Reencode::With(Box::new(Lz4::new(0)))),
+            (
+                addrs[1],
+                Reencode::With(Box::new(Pipeline::new(vec![
+                    Box::new(TrimAll::new()),
+                    Box::new(Lz4::new(0)),
+                ]))),
+            ),
+            (addrs[2], Reencode::With(Box::new(Zstd::new(3)))),
+        ]);
+
+        let mut rng = StdRng::seed_from_u64(25);
+        let txs: Vec<Transaction> = (0..2)
+            .map(|_| {
+                let (_, tx_bytes) = sender::random_transaction(&mut rng, 50);
+                Transaction::from_tx_bytes(&tx_bytes[..]).unwrap()
+            })
+            .collect();
+        let header = Header::new(vec![CodecId::Lz4], true);
+
+        // The batch came from the first neighbour, the others get one datagram per transaction
+        assert_eq!(2, gossip.relay(&node, addrs[0], &header, &txs));
+        assert!(receive_all(&neighbors[0]).is_empty());
+        for (neighbor, codec_ids) in neighbors[1..]
+            .iter()
+            .zip(&[vec![CodecId::TrimAll, CodecId::Lz4], vec![CodecId::Zstd]])
+        {
+            let received = receive_all(neighbor);
+            assert_eq!(2, received.len());
+
+            for ((header, relayed), tx) in received.iter().zip(&txs) {
+                assert_eq!(codec_ids, &header.codec_ids);
+                assert!(!header.batch);
+                assert_eq!(1, relayed.len());
+                assert_eq!(tx.as_tryte_string(), relayed[0].as_tryte_string());
+            }
+        }
+
+        // Transactions already relayed aren't sent again, wherever they come from
+        assert_eq!(0, gossip.relay(&node, addrs[1], &header, &txs));
+        for neighbor in &neighbors {
+            assert!(receive_all(neighbor).is_empty());
+        }
+    }
+
+    #[test]
+    fn seen_cache_forgets_the_oldest() {
+        let mut seen = SeenCache::new(2);
+
+        assert!(seen.insert(b"a"));
+        assert!(seen.insert(b"b"));
+        assert!(!seen.insert(b"a"));
+
+        // Pushes out "a"
+        assert!(seen.insert(b"c"));
+        assert!(!seen.insert(b"b"));
+        assert!(!seen.insert(b"c"));
+        assert!(seen.insert(b"a"));
+    }
+}
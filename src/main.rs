@@ -1,15 +1,35 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
 use structopt::StructOpt;
 
 mod algos;
+#[cfg(test)]
+mod allocations;
+mod bench;
 mod constants;
 mod convert;
+mod corpus;
+mod error;
+mod frame;
+mod gossip;
 mod model;
+mod net;
+mod ping;
 mod receiver;
 mod sender;
+mod sequence;
+mod stats;
 mod time;
+mod train;
+mod verify;
 
 use crate::algos::*;
-use crate::constants::{MAX_MESSAGE_LENGTH, MIN_MESSAGE_LENGTH};
+use crate::constants::{MAX_DATAGRAM_SIZE, MAX_MESSAGE_LENGTH, MIN_MESSAGE_LENGTH};
+use crate::error::Error;
 
 #[derive(Debug, StructOpt)]
 struct Args {
@@ -24,18 +44,47 @@ enum EndpointMode {
         about = "Send IOTA transactions to a receiver endpoint."
     )]
     Send {
-        /// Port of the sender.
-        #[structopt(short, default_value = "1337")]
-        send_port: u16,
+        /// Local address to send from, e.g. `[::1]:1337` or `myhost:1337`.
+        #[structopt(short, long, default_value = "127.0.0.1:1337")]
+        bind: String,
 
-        /// Port of the receiver.
-        #[structopt(short, default_value = "1338")]
-        recv_port: u16,
+        /// Address of the receiver. Resolves to one of the same family as the local address.
+        #[structopt(long, default_value = "127.0.0.1:1338")]
+        peer: String,
 
         /// Size of the payload stored in the signature message fragment.
         #[structopt(short, default_value = "1458")]
         payload_size: usize,
 
+        /// Pack as many transactions into one datagram as fit into this many bytes and compress
+        /// them jointly, at most 65507. Without it every datagram carries a single transaction.
+        #[structopt(long)]
+        mtu: Option<usize>,
+
+        /// Packets per second. Sends one packet every 3 seconds if omitted.
+        #[structopt(long)]
+        rate: Option<f64>,
+
+        /// Send without pausing between packets.
+        #[structopt(long, conflicts_with = "rate")]
+        flood: bool,
+
+        /// Packets sent back to back before pausing, the rate stays the same on average.
+        #[structopt(long, default_value = "1")]
+        burst: usize,
+
+        /// Stop after this many packets.
+        #[structopt(long)]
+        count: Option<u64>,
+
+        /// Stop after this many seconds.
+        #[structopt(long)]
+        duration: Option<f64>,
+
+        /// Don't print a line per packet, only the summary.
+        #[structopt(short, long)]
+        quiet: bool,
+
         /// The compression algorithm.
         #[structopt(subcommand)]
         algo: Algo,
@@ -46,13 +95,173 @@ enum EndpointMode {
         about = "Receive IOTA transactions from a sender endpoint."
     )]
     Recv {
-        /// Receiving port.
-        #[structopt(short, default_value = "1338")]
-        recv_port: u16,
+        /// Local address to receive on, e.g. `[::]:1338` or `myhost:1338`.
+        #[structopt(short, long, default_value = "127.0.0.1:1338")]
+        bind: String,
+
+        /// Dictionary file used to decompress Zstd packets.
+        #[structopt(short, parse(from_os_str))]
+        dictionary: Option<PathBuf>,
+
+        /// Tryte model file used to decompress Huffman packets.
+        #[structopt(short, parse(from_os_str))]
+        model: Option<PathBuf>,
+
+        /// Also print a summary of the last interval every this many seconds. A summary of the
+        /// whole run is printed on Ctrl-C.
+        #[structopt(long)]
+        interval: Option<f64>,
+
+        /// Don't print a line per packet, only the summaries.
+        #[structopt(short, long)]
+        quiet: bool,
+
+        /// Send every packet back to its sender, e.g. to answer `ping`.
+        #[structopt(long)]
+        echo: bool,
+
+        /// Relay new transactions to this neighbor, e.g. `127.0.0.1:1339=trimall+lz4`. Uses the
+        /// codecs of each incoming packet if the algorithm is omitted. Can be repeated.
+        #[structopt(long = "neighbor")]
+        neighbors: Vec<NeighborSpec>,
+
+        /// The compression algorithm of echoed packets. Uses the codecs of each incoming packet
+        /// if omitted.
+        #[structopt(subcommand)]
+        algo: Option<Algo>,
+    },
+
+    #[structopt(
+        name = "ping",
+        about = "Send IOTA transactions to a receiver in echo mode and check what comes back."
+    )]
+    Ping {
+        /// Local address to send from, e.g. `[::1]:1337` or `myhost:1337`.
+        #[structopt(short, long, default_value = "127.0.0.1:1337")]
+        bind: String,
+
+        /// Address of the receiver. Resolves to one of the same family as the local address.
+        #[structopt(long, default_value = "127.0.0.1:1338")]
+        peer: String,
+
+        /// Size of the payload stored in the signature message fragment.
+        #[structopt(short, default_value = "1458")]
+        payload_size: usize,
+
+        /// Pings per second. Sends one ping every second if omitted.
+        #[structopt(long)]
+        rate: Option<f64>,
+
+        /// Send the next ping as soon as the previous one was answered.
+        #[structopt(long, conflicts_with = "rate")]
+        flood: bool,
 
+        /// Stop after this many pings.
+        #[structopt(long)]
+        count: Option<u64>,
+
+        /// Stop after this many seconds.
+        #[structopt(long)]
+        duration: Option<f64>,
+
+        /// Milliseconds to wait for a reply before counting the ping as lost.
+        #[structopt(long, default_value = "1000")]
+        timeout: u64,
+
+        /// Dictionary file used to decompress Zstd replies.
+        #[structopt(short, parse(from_os_str))]
+        dictionary: Option<PathBuf>,
+
+        /// Tryte model file used to decompress Huffman replies.
+        #[structopt(short, parse(from_os_str))]
+        model: Option<PathBuf>,
+
+        /// The compression algorithm.
         #[structopt(subcommand)]
         algo: Algo,
     },
+
+    #[structopt(
+        name = "train",
+        about = "Train a compression dictionary from a file of transaction tryte strings."
+    )]
+    Train {
+        /// Corpus file with one transaction tryte string per line.
+        #[structopt(parse(from_os_str))]
+        corpus: PathBuf,
+
+        /// Output file of the trained dictionary.
+        #[structopt(short, parse(from_os_str), default_value = "itxc.dict")]
+        output: PathBuf,
+
+        /// Maximum size of the dictionary in bytes.
+        #[structopt(short, default_value = "16384")]
+        max_size: usize,
+
+        /// Percentage of the corpus held out to measure the compression ratio.
+        #[structopt(short, default_value = "10")]
+        test_percent: usize,
+
+        /// Compression level used to measure the compression ratio.
+        #[structopt(short, default_value = "3")]
+        compression_level: i32,
+
+        /// Also write a tryte model for the Huffman codec to this file.
+        #[structopt(long, parse(from_os_str))]
+        model: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "bench",
+        about = "Measure compression algorithms on a file of transaction tryte strings, without networking."
+    )]
+    Bench {
+        /// Corpus file with one transaction tryte string per line.
+        #[structopt(parse(from_os_str))]
+        corpus: PathBuf,
+
+        /// Algorithms written like pipelines, e.g. `zstd:19 trimall+lz4`. Measures all
+        /// algorithms across a sweep of levels if omitted.
+        algos: Vec<AlgoSpec>,
+
+        /// Dictionary file used by the Zstd algorithms.
+        #[structopt(short, parse(from_os_str))]
+        dictionary: Option<PathBuf>,
+
+        /// Tryte model file used by the Huffman algorithms.
+        #[structopt(short, parse(from_os_str))]
+        model: Option<PathBuf>,
+
+        /// How often every transaction is compressed and decompressed.
+        #[structopt(short, default_value = "3")]
+        rounds: usize,
+
+        /// Also write the results as CSV to this file.
+        #[structopt(long, parse(from_os_str))]
+        csv: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "verify",
+        about = "Check that every transaction of a file of tryte strings survives compression."
+    )]
+    Verify {
+        /// Corpus file with one transaction tryte string per line.
+        #[structopt(parse(from_os_str))]
+        corpus: PathBuf,
+
+        /// Algorithms written like pipelines, e.g. `zstd:19 trimall+lz4`. Verifies all
+        /// algorithms across a sweep of levels if omitted.
+        algos: Vec<AlgoSpec>,
+
+        /// Dictionary file used by the Zstd algorithms.
+        #[structopt(short, parse(from_os_str))]
+        dictionary: Option<PathBuf>,
+
+        /// Tryte model file used by the Huffman algorithms.
+        #[structopt(short, parse(from_os_str))]
+        model: Option<PathBuf>,
+    },
 }
 
 #[derive(Debug, StructOpt)]
@@ -63,11 +272,344 @@ enum Algo {
         compression_level: u32,
     },
 
+    #[structopt(
+        name = "lz4block",
+        about = "Use Lz4 compression algorithm in block mode (without frame)."
+    )]
+    Lz4Block {
+        #[structopt(short, default_value = "0")]
+        compression_level: u32,
+
+        /// Acceleration of the fast mode (levels below 3), trades ratio for speed.
+        #[structopt(short, default_value = "1")]
+        acceleration: i32,
+    },
+
+    #[structopt(name = "zstd", about = "Use Zstandard compression algorithm.")]
+    Zstd {
+        #[structopt(short, default_value = "3")]
+        compression_level: i32,
+
+        /// Dictionary file trained from transaction bytes.
+        #[structopt(short, parse(from_os_str))]
+        dictionary: Option<PathBuf>,
+    },
+
+    #[cfg(feature = "snappy")]
+    #[structopt(name = "snappy", about = "Use Snappy compression algorithm.")]
+    Snappy,
+
+    #[cfg(feature = "deflate")]
+    #[structopt(name = "deflate", about = "Use Deflate compression algorithm.")]
+    Deflate {
+        /// From 0 (store only) to 9 (best compression).
+        #[structopt(short, default_value = "6")]
+        compression_level: u32,
+    },
+
+    #[cfg(feature = "brotli")]
+    #[structopt(name = "brotli", about = "Use Brotli compression algorithm.")]
+    Brotli {
+        /// From 0 to 11 (best compression).
+        #[structopt(short, default_value = "11")]
+        compression_level: u32,
+
+        /// Base 2 logarithm of the window size, from 10 to 24.
+        #[structopt(short, default_value = "22")]
+        window_log: u32,
+    },
+
     #[structopt(name = "trimfrag", about = "Use Trim-Frag compression algorithm.")]
     TrimFrag,
 
     #[structopt(name = "trimall", about = "Use Trim-All compression algorithm.")]
     TrimAll,
+
+    #[structopt(
+        name = "sparse",
+        about = "Use Sparse compression algorithm (Trim-All with a field presence header)."
+    )]
+    Sparse,
+
+    #[structopt(
+        name = "pack5",
+        about = "Use Pack5 encoding (5 trits per byte), e.g. as first stage of a pipeline."
+    )]
+    Pack5,
+
+    #[structopt(
+        name = "hashref",
+        about = "Replace recently seen hashes with references (stateful, needs lossless delivery)."
+    )]
+    HashRef,
+
+    #[structopt(
+        name = "huffman",
+        about = "Use Huffman coding fitted to the tryte distribution of transactions."
+    )]
+    Huffman {
+        /// Tryte model file written by `train`. Uses the embedded model if omitted.
+        #[structopt(short, parse(from_os_str))]
+        model: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "tsdelta",
+        about = "Store the timelock and attachment timestamps as deltas to the issuance timestamp."
+    )]
+    TimestampDelta,
+
+    #[structopt(
+        name = "auto",
+        about = "Compress each transaction with every stateless algorithm and keep the smallest output."
+    )]
+    Auto {
+        /// Dictionary file used by the Zstd candidate.
+        #[structopt(short, parse(from_os_str))]
+        dictionary: Option<PathBuf>,
+
+        /// Tryte model file used by the Huffman candidate.
+        #[structopt(short, parse(from_os_str))]
+        model: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "pipe",
+        about = "Chain several compression algorithms, e.g. `trimall+lz4`."
+    )]
+    Pipe {
+        /// Algorithms separated by `+`. A level can follow a colon, e.g. `trimall+zstd:19`.
+        stages: PipelineSpec,
+
+        /// Dictionary file used by the Zstd stages.
+        #[structopt(short, parse(from_os_str))]
+        dictionary: Option<PathBuf>,
+
+        /// Tryte model file used by the Huffman stages.
+        #[structopt(short, parse(from_os_str))]
+        model: Option<PathBuf>,
+    },
+}
+
+impl Algo {
+    fn needs_tx_layout(&self) -> bool {
+        matches!(
+            self,
+            Algo::TrimFrag
+                | Algo::TrimAll
+                | Algo::Sparse
+                | Algo::Pack5
+                | Algo::HashRef
+                | Algo::Huffman { .. }
+                | Algo::TimestampDelta
+                | Algo::Auto { .. }
+        )
+    }
+}
+
+#[derive(Debug)]
+struct PipelineSpec(Vec<Algo>);
+
+impl FromStr for PipelineSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        PipelineSpec::new(spec.split('+').map(parse_stage).collect::<Result<_, _>>()?)
+    }
+}
+
+impl PipelineSpec {
+    fn new(stages: Vec<Algo>) -> Result<Self, String> {
+        if stages.is_empty() {
+            return Err("a pipeline needs at least one stage".to_string());
+        }
+
+        // These expect whole transaction bytes, which only the first stage gets to see
+        if let Some(stage) = stages.iter().skip(1).find(|stage| stage.needs_tx_layout()) {
+            return Err(format!("{:?} only works as the first stage", stage));
+        }
+
+        Ok(PipelineSpec(stages))
+    }
+
+    /// The stages that apply the given codecs at their default levels, or why there are none.
+    fn mirror(codec_ids: &[CodecId]) -> Result<Self, String> {
+        PipelineSpec::new(
+            codec_ids
+                .iter()
+                .map(|&id| mirror_stage(id))
+                .collect::<Result<_, _>>()?,
+        )
+    }
+}
+
+/// The stage that applies a codec at its default level, like `parse_stage` without a level.
+fn mirror_stage(id: CodecId) -> Result<Algo, String> {
+    Ok(match id {
+        CodecId::Lz4 => Algo::Lz4 {
+            compression_level: 0,
+        },
+        CodecId::Lz4Block => Algo::Lz4Block {
+            compression_level: 0,
+            acceleration: 1,
+        },
+        CodecId::Zstd => Algo::Zstd {
+            compression_level: 3,
+            dictionary: None,
+        },
+        #[cfg(feature = "snappy")]
+        CodecId::Snappy => Algo::Snappy,
+        #[cfg(feature = "deflate")]
+        CodecId::Deflate => Algo::Deflate {
+            compression_level: 6,
+        },
+        #[cfg(feature = "brotli")]
+        CodecId::Brotli => Algo::Brotli {
+            compression_level: 11,
+            window_log: 22,
+        },
+        CodecId::TrimFragment => Algo::TrimFrag,
+        CodecId::TrimAll => Algo::TrimAll,
+        CodecId::Sparse => Algo::Sparse,
+        CodecId::Pack5 => Algo::Pack5,
+        CodecId::HashRef => Algo::HashRef,
+        CodecId::Huffman => Algo::Huffman { model: None },
+        CodecId::TimestampDelta => Algo::TimestampDelta,
+        CodecId::Auto => Algo::Auto {
+            dictionary: None,
+            model: None,
+        },
+        #[allow(unreachable_patterns)]
+        _ => return Err(format!("{} isn't built into this binary", id)),
+    })
+}
+
+/// A pipeline to benchmark or verify, together with how it was written.
+#[derive(Debug)]
+struct AlgoSpec {
+    name: String,
+    stages: PipelineSpec,
+}
+
+impl FromStr for AlgoSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        Ok(AlgoSpec {
+            name: spec.to_string(),
+            stages: spec.parse()?,
+        })
+    }
+}
+
+/// A neighbor to relay to, written as `address[=pipeline]`.
+#[derive(Debug)]
+struct NeighborSpec {
+    addr: String,
+    stages: Option<PipelineSpec>,
+}
+
+impl FromStr for NeighborSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut parts = spec.splitn(2, '=');
+        let addr = parts.next().unwrap_or_default().to_string();
+        if addr.is_empty() {
+            return Err(format!("neighbor `{}` has no address", spec));
+        }
+
+        Ok(NeighborSpec {
+            addr,
+            stages: parts.next().map(str::parse).transpose()?,
+        })
+    }
+}
+
+/// What `bench` and `verify` run if no algorithms are given.
+fn default_algos() -> Vec<AlgoSpec> {
+    let mut specs = vec![
+        "lz4:0",
+        "lz4:3",
+        "lz4:9",
+        "lz4:12",
+        "lz4block:0",
+        "lz4block:3",
+        "lz4block:9",
+        "lz4block:12",
+        "zstd:1",
+        "zstd:3",
+        "zstd:6",
+        "zstd:9",
+    ];
+    #[cfg(feature = "snappy")]
+    specs.push("snappy");
+    #[cfg(feature = "deflate")]
+    specs.extend(&["deflate:1", "deflate:6", "deflate:9"]);
+    #[cfg(feature = "brotli")]
+    specs.extend(&["brotli:1", "brotli:5", "brotli:9", "brotli:11"]);
+    specs.extend(&[
+        "trimfrag", "trimall", "sparse", "pack5", "hashref", "huffman", "tsdelta", "auto",
+    ]);
+
+    specs
+        .into_iter()
+        .map(|spec| spec.parse().expect("invalid benchmark spec"))
+        .collect()
+}
+
+fn parse_stage(stage: &str) -> Result<Algo, String> {
+    let mut parts = stage.trim().splitn(2, ':');
+    let name = parts.next().unwrap_or_default();
+    let level = parts.next();
+
+    let algo = match name {
+        "lz4" => Algo::Lz4 {
+            compression_level: parse_level(stage, level, "0")?,
+        },
+        "lz4block" => Algo::Lz4Block {
+            compression_level: parse_level(stage, level, "0")?,
+            acceleration: 1,
+        },
+        "zstd" => Algo::Zstd {
+            compression_level: parse_level(stage, level, "3")?,
+            dictionary: None,
+        },
+        #[cfg(feature = "snappy")]
+        "snappy" => Algo::Snappy,
+        #[cfg(feature = "deflate")]
+        "deflate" => Algo::Deflate {
+            compression_level: parse_level(stage, level, "6")?,
+        },
+        #[cfg(feature = "brotli")]
+        "brotli" => Algo::Brotli {
+            compression_level: parse_level(stage, level, "11")?,
+            window_log: 22,
+        },
+        "trimfrag" => Algo::TrimFrag,
+        "trimall" => Algo::TrimAll,
+        "sparse" => Algo::Sparse,
+        "pack5" => Algo::Pack5,
+        "hashref" => Algo::HashRef,
+        "huffman" => Algo::Huffman { model: None },
+        "tsdelta" => Algo::TimestampDelta,
+        "auto" => Algo::Auto {
+            dictionary: None,
+            model: None,
+        },
+        _ => return Err(format!("unknown algorithm `{}`", name)),
+    };
+
+    match (&algo, level) {
+        (Algo::Lz4 { .. }, _) | (Algo::Lz4Block { .. }, _) | (Algo::Zstd { .. }, _) | (_, None) => {
+            Ok(algo)
+        }
+        #[cfg(feature = "deflate")]
+        (Algo::Deflate { .. }, _) => Ok(algo),
+        #[cfg(feature = "brotli")]
+        (Algo::Brotli { .. }, _) => Ok(algo),
+        _ => Err(format!("stage `{}` takes no level", stage)),
+    }
 }
 
 fn main() {
@@ -76,29 +618,122 @@ fn main() {
     println!("{:?}", cli);
 
     match cli.mode {
-        EndpointMode::Recv { recv_port, algo } => {
-            //
-            let algo: Box<dyn CompressionAlgo> = match algo {
-                Algo::Lz4 { compression_level } => Box::new(Lz4::new(compression_level)),
-                Algo::TrimFrag => Box::new(TrimFragment),
-                Algo::TrimAll => Box::new(TrimAll::new()),
+        EndpointMode::Recv {
+            bind,
+            dictionary,
+            model,
+            interval,
+            quiet,
+            neighbors,
+            echo,
+            algo,
+        } => {
+            let echo = match (echo, algo) {
+                (true, Some(algo)) => Some(crate::receiver::Reencode::With(create_algo(algo))),
+                (true, None) => Some(mirror(&dictionary, &model)),
+                (false, Some(_)) => {
+                    eprintln!("error: an algorithm can only be given with --echo");
+                    process::exit(1);
+                }
+                (false, None) => None,
             };
 
-            crate::receiver::start(recv_port, algo);
+            let neighbors = neighbors
+                .into_iter()
+                .map(|neighbor| {
+                    let codec = match neighbor.stages {
+                        Some(stages) => crate::receiver::Reencode::With(create_pipeline(
+                            stages,
+                            &dictionary,
+                            &model,
+                        )),
+                        None => mirror(&dictionary, &model),
+                    };
+                    (neighbor.addr, codec)
+                })
+                .collect();
+
+            let dictionary = dictionary
+                .map(|path| fs::read(path).expect("Couldn't read dictionary file"))
+                .unwrap_or_default();
+
+            let interval = interval.map(|secs| match Duration::try_from_secs_f64(secs) {
+                Ok(interval) if !interval.is_zero() => interval,
+                _ => {
+                    eprintln!("error: --interval must be a positive number");
+                    process::exit(1);
+                }
+            });
+
+            // Every sender gets decoders of its own
+            let model = load_model(model);
+            crate::receiver::start(
+                &bind,
+                crate::receiver::Registries::new(move || Registry::new(dictionary.clone(), &model)),
+                interval,
+                quiet,
+                echo,
+                neighbors,
+            );
         }
-        EndpointMode::Send {
-            send_port,
-            recv_port,
+        EndpointMode::Ping {
+            bind,
+            peer,
             payload_size,
+            rate,
+            flood,
+            count,
+            duration,
+            timeout,
+            dictionary,
+            model,
             algo,
         } => {
-            //
-            let algo: Box<dyn CompressionAlgo> = match algo {
-                Algo::Lz4 { compression_level } => Box::new(Lz4::new(compression_level)),
-                Algo::TrimFrag => Box::new(TrimFragment),
-                Algo::TrimAll => Box::new(TrimAll::new()),
+            let algo = create_algo(algo);
+            let payload_size = payload_size.clamp(MIN_MESSAGE_LENGTH, MAX_MESSAGE_LENGTH);
+            let traffic = crate::sender::Traffic {
+                rate: if flood {
+                    None
+                } else {
+                    Some(positive_rate(rate.unwrap_or(crate::ping::DEFAULT_RATE), 1))
+                },
+                burst: 1,
+                count,
+                duration: duration.map(duration_from_secs),
             };
 
+            let dictionary = dictionary
+                .map(|path| fs::read(path).expect("Couldn't read dictionary file"))
+                .unwrap_or_default();
+            let registry = Registry::new(dictionary, &load_model(model));
+
+            if !crate::ping::start(
+                &bind,
+                &peer,
+                payload_size,
+                traffic,
+                Duration::from_millis(timeout),
+                algo,
+                registry,
+            ) {
+                process::exit(1);
+            }
+        }
+        EndpointMode::Send {
+            bind,
+            peer,
+            payload_size,
+            mtu,
+            rate,
+            flood,
+            burst,
+            count,
+            duration,
+            quiet,
+            algo,
+        } => {
+            let algo = create_algo(algo);
+
             let payload_size = if payload_size < MIN_MESSAGE_LENGTH {
                 MIN_MESSAGE_LENGTH
             } else if payload_size > MAX_MESSAGE_LENGTH {
@@ -107,7 +742,295 @@ fn main() {
                 payload_size
             };
 
-            crate::sender::start(send_port, recv_port, payload_size, algo);
+            let rate = if flood {
+                None
+            } else {
+                Some(positive_rate(
+                    rate.unwrap_or(crate::sender::DEFAULT_RATE),
+                    burst,
+                ))
+            };
+
+            let traffic = crate::sender::Traffic {
+                rate,
+                burst,
+                count,
+                duration: duration.map(duration_from_secs),
+            };
+
+            crate::sender::start(
+                &bind,
+                &peer,
+                payload_size,
+                mtu.map(valid_mtu),
+                traffic,
+                quiet,
+                algo,
+            );
+        }
+        EndpointMode::Train {
+            corpus,
+            output,
+            max_size,
+            test_percent,
+            compression_level,
+            model,
+        } => {
+            let test_percent = test_percent.min(100);
+
+            if !crate::train::start(
+                &corpus,
+                &output,
+                max_size,
+                test_percent,
+                compression_level,
+                model.as_deref(),
+            ) {
+                process::exit(1);
+            }
+        }
+        EndpointMode::Bench {
+            corpus,
+            algos,
+            dictionary,
+            model,
+            rounds,
+            csv,
+        } => {
+            let algos = create_named_algos(algos, &dictionary, &model);
+            let dictionary = dictionary
+                .map(|path| fs::read(path).expect("Couldn't read dictionary file"))
+                .unwrap_or_default();
+
+            if !crate::bench::start(
+                &corpus,
+                algos,
+                dictionary,
+                &load_model(model),
+                rounds,
+                csv.as_deref(),
+            ) {
+                process::exit(1);
+            }
+        }
+        EndpointMode::Verify {
+            corpus,
+            algos,
+            dictionary,
+            model,
+        } => {
+            let algos = create_named_algos(algos, &dictionary, &model);
+            let dictionary = dictionary
+                .map(|path| fs::read(path).expect("Couldn't read dictionary file"))
+                .unwrap_or_default();
+
+            if !crate::verify::start(&corpus, algos, dictionary, &load_model(model)) {
+                process::exit(1);
+            }
         }
     }
 }
+
+fn parse_level<T: FromStr>(stage: &str, level: Option<&str>, default: &str) -> Result<T, String> {
+    level
+        .unwrap_or(default)
+        .parse()
+        .map_err(|_| format!("invalid level in stage `{}`", stage))
+}
+
+/// Exits with an error unless the rate is a positive number and large enough for the pause
+/// between bursts to be representable.
+fn positive_rate(rate: f64, burst: usize) -> f64 {
+    // The pause between bursts must fit into a `Duration`
+    let interval = Duration::try_from_secs_f64(burst.max(1) as f64 / rate);
+    if rate > 0.0 && rate.is_finite() && interval.is_ok() {
+        return rate;
+    }
+
+    eprintln!("error: --rate must be a positive number and not too small");
+    process::exit(1);
+}
+
+/// Checks `--mtu`, exits with an error if no UDP datagram can be that large.
+fn valid_mtu(mtu: usize) -> usize {
+    if mtu <= MAX_DATAGRAM_SIZE {
+        return mtu;
+    }
+
+    eprintln!("error: --mtu must be at most {}", MAX_DATAGRAM_SIZE);
+    process::exit(1);
+}
+
+/// Parses `--duration`, exits with an error if it is negative or too large.
+fn duration_from_secs(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or_else(|_| {
+        eprintln!("error: --duration must be a non-negative number");
+        process::exit(1);
+    })
+}
+
+fn load_model(path: Option<PathBuf>) -> TryteModel {
+    path.map(|path| TryteModel::load(&path).expect("Couldn't read tryte model file"))
+        .unwrap_or_default()
+}
+
+fn create_algo(algo: Algo) -> Box<dyn CompressionAlgo> {
+    match algo {
+        Algo::Lz4 { compression_level } => Box::new(Lz4::new(compression_level)),
+        Algo::Lz4Block {
+            compression_level,
+            acceleration,
+        } => Box::new(Lz4Block::new(compression_level, acceleration)),
+        Algo::Zstd {
+            compression_level,
+            dictionary,
+        } => match dictionary {
+            Some(path) => {
+                let dictionary = fs::read(path).expect("Couldn't read dictionary file");
+                Box::new(Zstd::with_dictionary(compression_level, dictionary))
+            }
+            None => Box::new(Zstd::new(compression_level)),
+        },
+        #[cfg(feature = "snappy")]
+        Algo::Snappy => Box::new(Snappy::new()),
+        #[cfg(feature = "deflate")]
+        Algo::Deflate { compression_level } => Box::new(Deflate::new(compression_level)),
+        #[cfg(feature = "brotli")]
+        Algo::Brotli {
+            compression_level,
+            window_log,
+        } => Box::new(Brotli::new(compression_level, window_log)),
+        Algo::TrimFrag => Box::new(TrimFragment),
+        Algo::TrimAll => Box::new(TrimAll::new()),
+        Algo::Sparse => Box::new(Sparse),
+        Algo::Pack5 => Box::new(Pack5),
+        Algo::HashRef => Box::new(HashRef::new()),
+        Algo::Huffman { model } => Box::new(Huffman::new(&load_model(model))),
+        Algo::TimestampDelta => Box::new(TimestampDelta),
+        Algo::Auto { dictionary, model } => {
+            let dictionary = dictionary
+                .map(|path| fs::read(path).expect("Couldn't read dictionary file"))
+                .unwrap_or_default();
+
+            Box::new(Auto::new(dictionary, &load_model(model)))
+        }
+        Algo::Pipe {
+            stages,
+            dictionary,
+            model,
+        } => Box::new(Pipeline::new(
+            stages
+                .0
+                .into_iter()
+                .map(|stage| create_stage(stage, &dictionary, &model))
+                .collect(),
+        )),
+    }
+}
+
+/// Creates the given algorithms along with their names, or the default ones if none are given.
+fn create_named_algos(
+    specs: Vec<AlgoSpec>,
+    dictionary: &Option<PathBuf>,
+    model: &Option<PathBuf>,
+) -> Vec<(String, Box<dyn CompressionAlgo>)> {
+    let specs = if specs.is_empty() {
+        default_algos()
+    } else {
+        specs
+    };
+
+    specs
+        .into_iter()
+        .map(|spec| (spec.name, create_pipeline(spec.stages, dictionary, model)))
+        .collect()
+}
+
+/// Re-encodes with the codecs of each incoming packet, at their default levels. Packets whose
+/// codecs can't be applied in that order fail to re-encode.
+fn mirror(dictionary: &Option<PathBuf>, model: &Option<PathBuf>) -> crate::receiver::Reencode {
+    let (dictionary, model) = (dictionary.clone(), model.clone());
+
+    crate::receiver::Reencode::mirror(move |codec_ids| {
+        let stages = PipelineSpec::mirror(codec_ids).map_err(Error::InvalidPipeline)?;
+        Ok(create_pipeline(stages, &dictionary, &model))
+    })
+}
+
+/// Like a `pipe`, but a single stage isn't wrapped into a `Pipeline`.
+fn create_pipeline(
+    mut stages: PipelineSpec,
+    dictionary: &Option<PathBuf>,
+    model: &Option<PathBuf>,
+) -> Box<dyn CompressionAlgo> {
+    if stages.0.len() == 1 {
+        return create_stage(stages.0.remove(0), dictionary, model);
+    }
+
+    create_algo(Algo::Pipe {
+        stages,
+        dictionary: dictionary.clone(),
+        model: model.clone(),
+    })
+}
+
+/// Creates a pipeline stage, which uses the dictionary and model of its pipeline.
+fn create_stage(
+    stage: Algo,
+    dictionary: &Option<PathBuf>,
+    model: &Option<PathBuf>,
+) -> Box<dyn CompressionAlgo> {
+    match stage {
+        Algo::Zstd {
+            compression_level, ..
+        } => create_algo(Algo::Zstd {
+            compression_level,
+            dictionary: dictionary.clone(),
+        }),
+        Algo::Huffman { .. } => create_algo(Algo::Huffman {
+            model: model.clone(),
+        }),
+        Algo::Auto { .. } => create_algo(Algo::Auto {
+            dictionary: dictionary.clone(),
+            model: model.clone(),
+        }),
+        stage => create_algo(stage),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::frame::Header;
+    use crate::model::transaction::Transaction;
+
+    #[test]
+    fn mirror_rejects_codecs_it_cant_chain() {
+        assert!(PipelineSpec::mirror(&[]).is_err());
+        assert!(PipelineSpec::mirror(&[CodecId::Lz4, CodecId::TrimAll]).is_err());
+        assert!(PipelineSpec::mirror(&[CodecId::TrimAll, CodecId::Lz4]).is_ok());
+
+        // Such datagrams fail to re-encode instead of taking down the receiver
+        let mut rng = StdRng::seed_from_u64(24);
+        let (_, tx_bytes) = crate::sender::random_transaction(&mut rng, 100);
+        let txs = vec![Transaction::from_tx_bytes(&tx_bytes[..]).unwrap()];
+        let mut mirror = mirror(&None, &None);
+        let peer = ([127, 0, 0, 1], 1).into();
+        let mut datagram = Vec::new();
+
+        for codec_ids in &[vec![], vec![CodecId::Lz4, CodecId::TrimAll]] {
+            let header = Header::new(codec_ids.clone(), false);
+            assert!(mirror.encode(peer, &header, &txs, &mut datagram).is_err());
+        }
+
+        let header = Header::new(vec![CodecId::TrimAll, CodecId::Lz4], false);
+        mirror.encode(peer, &header, &txs, &mut datagram).unwrap();
+        let mut mirrored = Header::default();
+        mirrored.read_into(&datagram).unwrap();
+        assert_eq!(header.codec_ids, mirrored.codec_ids);
+    }
+}
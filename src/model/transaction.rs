@@ -4,6 +4,7 @@ use crate::convert::number;
 use crate::convert::trits::{self, *};
 use crate::convert::tryte_string;
 use crate::convert::trytes::{self, *};
+use crate::error::{Error, Result};
 use crate::time;
 
 pub const MAX_TIME_TRYTE_LENGTH: usize = 9;
@@ -28,78 +29,111 @@ pub struct Transaction {
 }
 
 impl Transaction {
-    pub fn from_tx_bytes(bytes: &[u8]) -> Self {
-        Transaction::from_tx_trytes(&trytes::from_tx_bytes_2enc9(bytes))
+    pub fn from_tx_bytes(bytes: &[u8]) -> Result<Self> {
+        check_length(bytes.len(), TRANSACTION_SIZE_BYTES)?;
+        if !bytes::is_valid_2enc9(bytes) {
+            return Err(Error::InvalidTryte);
+        }
+
+        Ok(Transaction::from_tx_trytes(&trytes::from_tx_bytes_2enc9(
+            bytes,
+        )))
     }
 
-    pub fn from_tryte_string(tryte_string: &str) -> Self {
-        assert!(IS_TRYTES.is_match(tryte_string));
+    pub fn from_tryte_string(tryte_string: &str) -> Result<Self> {
+        if !IS_TRYTES.is_match(tryte_string) {
+            return Err(Error::InvalidTryte);
+        }
         let bytes = tryte_string.as_bytes();
-        assert_eq!(bytes.len(), TRANSACTION_SIZE_TRYTES);
+        check_length(bytes.len(), TRANSACTION_SIZE_TRYTES)?;
 
         let mut trytes = [0; TRANSACTION_SIZE_TRYTES];
         trytes[..].copy_from_slice(&bytes[..]);
 
-        Transaction::from_tx_trytes(&trytes)
+        Ok(Transaction::from_tx_trytes(&trytes))
     }
 
     pub fn from_tx_trytes(trytes: &TxTrytes) -> Self {
-        let signature_fragments =
-            tryte_string::from_trytes(&trytes[SIGNATURE_FRAGMENTS.2..EXTRA_DATA_DIGEST.2]);
+        let mut tx = Transaction {
+            signature_fragments: String::new(),
+            extra_data_digest: String::new(),
+            address: String::new(),
+            value: 0,
+            issuance_timestamp: 0,
+            timelock_lower_bound: 0,
+            timelock_upper_bound: 0,
+            bundle_nonce: String::new(),
+            trunk: String::new(),
+            branch: String::new(),
+            tag: String::new(),
+            attachment_timestamp: 0,
+            attachment_timestamp_lower_bound: 0,
+            attachment_timestamp_upper_bound: 0,
+            nonce: String::new(),
+        };
+        tx.read_tx_trytes(trytes);
+        tx
+    }
 
-        let extra_data_digest = tryte_string::from_trytes(&trytes[EXTRA_DATA_DIGEST.2..ADDRESS.2]);
+    /// Like `from_tx_bytes`, but overwrites this transaction and reuses the memory of its
+    /// strings. On error the transaction is left unchanged.
+    pub fn read_tx_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        check_length(bytes.len(), TRANSACTION_SIZE_BYTES)?;
+        if !bytes::is_valid_2enc9(bytes) {
+            return Err(Error::InvalidTryte);
+        }
 
-        let address = tryte_string::from_trytes(&trytes[ADDRESS.2..VALUE.2]);
+        self.read_tx_trytes(&trytes::from_tx_bytes_2enc9(bytes));
+        Ok(())
+    }
 
-        let value = number::i64_from_trytes_max11(&trytes[VALUE.2..ISSUANCE_TIMESTAMP.2]);
+    fn read_tx_trytes(&mut self, trytes: &TxTrytes) {
+        set_trytes(
+            &mut self.signature_fragments,
+            &trytes[SIGNATURE_FRAGMENTS.2..EXTRA_DATA_DIGEST.2],
+        );
+
+        set_trytes(
+            &mut self.extra_data_digest,
+            &trytes[EXTRA_DATA_DIGEST.2..ADDRESS.2],
+        );
 
-        let issuance_timestamp =
+        set_trytes(&mut self.address, &trytes[ADDRESS.2..VALUE.2]);
+
+        self.value = number::i64_from_trytes_max11(&trytes[VALUE.2..ISSUANCE_TIMESTAMP.2]);
+
+        self.issuance_timestamp =
             number::i64_from_trytes_max11(&trytes[ISSUANCE_TIMESTAMP.2..TIMELOCK_LOWER_BOUND.2]);
 
-        let timelock_lower_bound =
+        self.timelock_lower_bound =
             number::i64_from_trytes_max11(&trytes[TIMELOCK_LOWER_BOUND.2..TIMELOCK_UPPER_BOUND.2]);
 
-        let timelock_upper_bound =
+        self.timelock_upper_bound =
             number::i64_from_trytes_max11(&trytes[TIMELOCK_UPPER_BOUND.2..BUNDLE_NONCE.2]);
 
-        let bundle_nonce = tryte_string::from_trytes(&trytes[BUNDLE_NONCE.2..TRUNK_HASH.2]);
+        set_trytes(
+            &mut self.bundle_nonce,
+            &trytes[BUNDLE_NONCE.2..TRUNK_HASH.2],
+        );
 
-        let trunk = tryte_string::from_trytes(&trytes[TRUNK_HASH.2..BRANCH_HASH.2]);
+        set_trytes(&mut self.trunk, &trytes[TRUNK_HASH.2..BRANCH_HASH.2]);
 
-        let branch = tryte_string::from_trytes(&trytes[BRANCH_HASH.2..TAG.2]);
+        set_trytes(&mut self.branch, &trytes[BRANCH_HASH.2..TAG.2]);
 
-        let tag = tryte_string::from_trytes(&trytes[TAG.2..ATTACHMENT_TIMESTAMP.2]);
+        set_trytes(&mut self.tag, &trytes[TAG.2..ATTACHMENT_TIMESTAMP.2]);
 
-        let attachment_timestamp = number::i64_from_trytes_max11(
+        self.attachment_timestamp = number::i64_from_trytes_max11(
             &trytes[ATTACHMENT_TIMESTAMP.2..ATTACHMENT_TIMESTAMP_LOWER_BOUND.2],
         );
 
-        let attachment_timestamp_lower_bound = number::i64_from_trytes_max11(
+        self.attachment_timestamp_lower_bound = number::i64_from_trytes_max11(
             &trytes[ATTACHMENT_TIMESTAMP_LOWER_BOUND.2..ATTACHMENT_TIMESTAMP_UPPER_BOUND.2],
         );
 
-        let attachment_timestamp_upper_bound =
+        self.attachment_timestamp_upper_bound =
             number::i64_from_trytes_max11(&trytes[ATTACHMENT_TIMESTAMP_UPPER_BOUND.2..NONCE.2]);
 
-        let nonce = tryte_string::from_trytes(&trytes[NONCE.2..TRANSACTION_SIZE_TRYTES]);
-
-        Transaction {
-            signature_fragments,
-            extra_data_digest,
-            address,
-            value,
-            issuance_timestamp,
-            timelock_lower_bound,
-            timelock_upper_bound,
-            bundle_nonce,
-            trunk,
-            branch,
-            tag,
-            attachment_timestamp,
-            attachment_timestamp_lower_bound,
-            attachment_timestamp_upper_bound,
-            nonce,
-        }
+        set_trytes(&mut self.nonce, &trytes[NONCE.2..TRANSACTION_SIZE_TRYTES]);
     }
 
     pub fn as_bytes(&self) -> TxBytes {
@@ -161,6 +195,24 @@ impl Transaction {
         trytes
     }
 
+    /// The name of the first field whose trytes differ from the ones of `other`, along with the
+    /// offset of the first differing tryte within that field.
+    pub fn first_difference(&self, other: &Transaction) -> Option<(&'static str, usize)> {
+        let (trytes, other_trytes) = (self.as_trytes(), other.as_trytes());
+
+        FIELDS
+            .iter()
+            .zip(FIELD_NAMES.iter())
+            .find_map(|(field, name)| {
+                let range = field.2..field.2 + field.3;
+                trytes[range.clone()]
+                    .iter()
+                    .zip(&other_trytes[range])
+                    .position(|(a, b)| a != b)
+                    .map(|offset| (*name, offset))
+            })
+    }
+
     /*
     pub fn get_hash(&self) -> Trytes81 {
         trytes::from_trits_fixed81(&curl::curl_tx(
@@ -170,20 +222,45 @@ impl Transaction {
     }
     */
 
-    pub fn message(mut self, message: &str) -> Self {
-        assert!(message.len() <= SIGNATURE_FRAGMENTS.3);
+    pub fn message(mut self, message: &str) -> Result<Self> {
+        if !message.is_ascii() {
+            return Err(Error::InvalidTryte);
+        }
+        if message.len() > MAX_MESSAGE_LENGTH {
+            return Err(Error::OutputOverflow);
+        }
 
         self.signature_fragments =
             tryte_string::pad_right(&tryte_string::from_ascii(message), SIGNATURE_FRAGMENTS.3);
-        self
+        Ok(self)
     }
 
-    pub fn tag(mut self, tag: &str) -> Self {
-        assert!(IS_TRYTES.is_match(tag));
-        assert!(tag.len() <= TAG.3);
+    pub fn tag(mut self, tag: &str) -> Result<Self> {
+        if !IS_TRYTES.is_match(tag) {
+            return Err(Error::InvalidTryte);
+        }
+        if tag.len() > TAG.3 {
+            return Err(Error::OutputOverflow);
+        }
 
         self.tag = tryte_string::pad_right(tag, TAG.3);
-        self
+        Ok(self)
+    }
+}
+
+/// Replaces the content of a tryte string field without giving up its memory.
+fn set_trytes(field: &mut String, trytes: &[Tryte]) {
+    field.clear();
+    field.push_str(std::str::from_utf8(trytes).unwrap());
+}
+
+fn check_length(actual: usize, expected: usize) -> Result<()> {
+    if actual < expected {
+        Err(Error::TruncatedInput)
+    } else if actual > expected {
+        Err(Error::OutputOverflow)
+    } else {
+        Ok(())
     }
 }
 
@@ -273,13 +350,68 @@ mod tests {
     #[test]
     fn test_transaction_decoding() {
         let example_trytes = get_example_trytes();
-        let tx = Transaction::from_tryte_string(&example_trytes);
+        let tx = Transaction::from_tryte_string(&example_trytes).unwrap();
 
         assert_eq!(EXAMPLE_ADDR, tx.address);
         assert_eq!(-7_297_419_313, tx.value);
         assert_eq!(1_544_207_541_879, tx.attachment_timestamp);
     }
 
+    #[test]
+    fn test_transaction_decoding_rejects_invalid_input() {
+        let example_trytes = get_example_trytes();
+
+        assert!(Transaction::from_tryte_string(&example_trytes[1..]).is_err());
+        assert!(Transaction::from_tryte_string(&example_trytes.to_lowercase()).is_err());
+
+        let bytes = Transaction::from_tryte_string(&example_trytes)
+            .unwrap()
+            .as_bytes();
+        assert!(Transaction::from_tx_bytes(&bytes[..1000]).is_err());
+
+        let mut invalid_bytes = bytes;
+        invalid_bytes[0] = 0xFF;
+        assert!(Transaction::from_tx_bytes(&invalid_bytes).is_err());
+    }
+
+    #[test]
+    fn test_transaction_message_and_tag_are_checked() {
+        let tx = Transaction::default();
+
+        assert!(tx
+            .clone()
+            .message(&"a".repeat(MAX_MESSAGE_LENGTH + 1))
+            .is_err());
+        assert!(tx.clone().message("Grüße").is_err());
+        assert!(tx.clone().tag("NOT9TRYTES!").is_err());
+        assert!(tx.clone().tag(&"A".repeat(TAG.3 + 1)).is_err());
+        assert_eq!("HELLO", &tx.tag("HELLO").unwrap().tag[..5]);
+    }
+
+    #[test]
+    fn test_transaction_first_difference() {
+        let tx = Transaction::from_tryte_string(&get_example_trytes()).unwrap();
+        assert_eq!(None, tx.first_difference(&tx.clone()));
+
+        let mut other = tx.clone();
+        other.tag = tryte_string::pad_right("ABC", TAG.3);
+        other.nonce = TRYTE_NULL_STR.repeat(NONCE.3);
+        assert_eq!(Some(("tag", 0)), tx.first_difference(&other));
+
+        let mut other = tx.clone();
+        other.attachment_timestamp += 1;
+        assert_eq!(
+            Some(("attachment_timestamp", 0)),
+            tx.first_difference(&other)
+        );
+
+        let other = tx.clone().message("Hello").unwrap();
+        assert_eq!(
+            Some(("signature_fragments", 0)),
+            tx.first_difference(&other)
+        );
+    }
+
     fn get_example_trytes() -> String {
         let sig_msg_frag = MAINNET_TRYTES.get(0..2187).unwrap();
         let extra_data_digest = MAINNET_TRYTES.get((2187 + 162)..(2187 + 162 + 81)).unwrap(); //copied bundle hash
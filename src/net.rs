@@ -0,0 +1,43 @@
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+/// Resolves `addr` to an address a socket bound to `local` can send to, i.e. one of the same
+/// family. Hostnames go through the local resolver, which may return both IPv4 and IPv6
+/// addresses.
+pub fn resolve_peer(addr: &str, local: SocketAddr) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?
+        .find(|peer| peer.is_ipv4() == local.is_ipv4())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no address of the same family as {}", addr, local),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_peer_matches_the_family() {
+        let v4: SocketAddr = "127.0.0.1:1337".parse().unwrap();
+        let v6: SocketAddr = "[::1]:1337".parse().unwrap();
+
+        assert_eq!(
+            "127.0.0.1:1338".parse::<SocketAddr>().unwrap(),
+            resolve_peer("127.0.0.1:1338", v4).unwrap()
+        );
+        assert_eq!(
+            "[::1]:1338".parse::<SocketAddr>().unwrap(),
+            resolve_peer("[::1]:1338", v6).unwrap()
+        );
+        assert!(resolve_peer("[::1]:1338", v4).is_err());
+        assert!(resolve_peer("127.0.0.1:1338", v6).is_err());
+        assert!(resolve_peer("127.0.0.1", v4).is_err());
+
+        let peer = resolve_peer("localhost:1338", v4).unwrap();
+        assert!(peer.ip().is_loopback() && peer.is_ipv4());
+        assert_eq!(1338, peer.port());
+    }
+}
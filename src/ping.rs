@@ -0,0 +1,213 @@
+use rand::thread_rng;
+
+use std::io;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::algos::{CompressionAlgo, Registry};
+use crate::constants::MAX_DATAGRAM_SIZE;
+use crate::frame::Header;
+use crate::model::transaction::*;
+use crate::net;
+use crate::receiver;
+use crate::sender::{self, Pacer, Traffic};
+use crate::stats::{micros, Histogram};
+use crate::time;
+
+/// One ping per second, like the classic tool.
+pub const DEFAULT_RATE: f64 = 1.0;
+
+/// What became of the pings sent so far.
+#[derive(Default)]
+struct Tally {
+    sent: u64,
+    matched: u64,
+    mismatched: u64,
+    lost: u64,
+    late: u64,
+    undecodable: u64,
+}
+
+/// Sends one transaction at a time to a receiver in echo mode and waits up to `timeout` for it
+/// to come back. Every reply is decompressed and compared with the transaction that was sent,
+/// which checks both codec directions over a real socket. Stops on Ctrl-C or when the traffic
+/// limits are reached and prints round-trip time percentiles. Returns whether every reply
+/// matched its transaction.
+pub fn start(
+    bind_addr: &str,
+    peer_addr: &str,
+    msg_length: usize,
+    traffic: Traffic,
+    timeout: Duration,
+    algo: Box<dyn CompressionAlgo>,
+    registry: Registry,
+) -> bool {
+    let socket = UdpSocket::bind(bind_addr).expect("Couldn't bind to ping address");
+    let local_addr = socket.local_addr().expect("Couldn't get ping address");
+    let peer_addr =
+        net::resolve_peer(peer_addr, local_addr).expect("Couldn't resolve receiver address");
+
+    // Print the summary instead of dying on Ctrl-C
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&interrupted);
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst))
+        .expect("Couldn't set Ctrl-C handler");
+
+    let mut rng = thread_rng();
+    let mut header = Header::new(algo.codec_ids(), false);
+    let mut datagram = Vec::with_capacity(MAX_DATAGRAM_SIZE);
+    let mut buf = [0; MAX_DATAGRAM_SIZE];
+    let mut decoded = receiver::Decoded::default();
+
+    let start = Instant::now();
+    let mut pacer = Pacer::new(traffic.rate, traffic.burst);
+    let deadline = traffic.deadline(start);
+    let mut tally = Tally::default();
+    let mut rtts = Histogram::new();
+
+    while !interrupted.load(Ordering::SeqCst)
+        && traffic.count.is_none_or(|count| tally.sent < count)
+        && traffic
+            .duration
+            .is_none_or(|duration| start.elapsed() < duration)
+    {
+        let (_, tx_bytes) = sender::random_transaction(&mut rng, msg_length);
+        let tx = Transaction::from_tx_bytes(&tx_bytes[..]).expect("error creating transaction");
+
+        header.seq = tally.sent as u32;
+        datagram.clear();
+        header.write(&mut datagram);
+        let compress_start = Instant::now();
+        algo.compress_into(&tx_bytes[..], &mut datagram)
+            .expect("error compressing transaction");
+        header.compress_ns = compress_start
+            .elapsed()
+            .as_nanos()
+            .min(u128::from(u32::MAX)) as u32;
+
+        if !pacer.wait(deadline) {
+            break;
+        }
+        header.sent_at = time::get_monotonic_time_nanos();
+        header.rewrite(&mut datagram);
+        let sent = Instant::now();
+        socket
+            .send_to(&datagram, peer_addr)
+            .expect("Couldn't send packet to receiver");
+        tally.sent += 1;
+
+        // Wait for the reply to this ping, replies to earlier ones are late
+        let deadline = sent + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline || interrupted.load(Ordering::SeqCst) {
+                println!(
+                    "No reply to #{} within {} ms",
+                    header.seq,
+                    timeout.as_millis()
+                );
+                tally.lost += 1;
+                break;
+            }
+
+            socket
+                .set_read_timeout(Some(deadline - now))
+                .expect("Couldn't set read timeout");
+            let (num_bytes, src_addr) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(ref e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::Interrupted
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => panic!("Couldn't receive reply: {}", e),
+            };
+            let rtt = sent.elapsed();
+
+            if src_addr != peer_addr {
+                continue;
+            }
+
+            let received_at = time::get_monotonic_time_nanos();
+            if let Err(e) =
+                receiver::decode(&registry, &buf[..num_bytes], received_at, &mut decoded)
+            {
+                println!("Couldn't decode reply of {} bytes: {}", num_bytes, e);
+                tally.undecodable += 1;
+                continue;
+            }
+            let (reply, txs) = (&decoded.header, decoded.txs());
+
+            if reply.seq != header.seq {
+                println!("Late reply to #{}", reply.seq);
+                tally.late += 1;
+                continue;
+            }
+
+            let codecs: Vec<String> = reply.codec_ids.iter().map(|id| id.to_string()).collect();
+            match mismatch(&tx, txs) {
+                None => {
+                    println!(
+                        "Reply of {} bytes to #{} via {} - RTT {:.2} µs",
+                        num_bytes,
+                        reply.seq,
+                        codecs.join("+"),
+                        micros(rtt),
+                    );
+                    tally.matched += 1;
+                    rtts.add(rtt);
+                }
+                Some(mismatch) => {
+                    println!(
+                        "Reply to #{} via {}: {}",
+                        reply.seq,
+                        codecs.join("+"),
+                        mismatch
+                    );
+                    tally.mismatched += 1;
+                }
+            }
+            break;
+        }
+    }
+
+    println!(
+        "Sent {} pings: {} matched, {} mismatched, {} lost, {} late, {} undecodable replies.",
+        tally.sent, tally.matched, tally.mismatched, tally.lost, tally.late, tally.undecodable,
+    );
+    if let Some(summary) = rtts.summary() {
+        println!(
+            "RTT {:.2}/{:.2}/{:.2}/{:.2} µs (p50/p90/p99/max)",
+            micros(summary.p50),
+            micros(summary.p90),
+            micros(summary.p99),
+            micros(summary.max),
+        );
+    }
+
+    tally.mismatched == 0 && tally.undecodable == 0
+}
+
+/// Describes how the echoed transactions differ from the one that was sent, if they do.
+fn mismatch(sent: &Transaction, echoed: &[Transaction]) -> Option<String> {
+    let echoed = match echoed {
+        [echoed] => echoed,
+        _ => return Some(format!("{} transactions instead of 1", echoed.len())),
+    };
+
+    if echoed.as_tryte_string() == sent.as_tryte_string() {
+        return None;
+    }
+
+    Some(match sent.first_difference(echoed) {
+        Some((field, offset)) => format!("{} differs at tryte {}", field, offset),
+        None => "tryte strings differ".to_string(),
+    })
+}
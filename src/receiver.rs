@@ -1,37 +1,854 @@
 use crate::algos::*;
 use crate::constants::*;
 use crate::convert::ascii;
+use crate::error::{Error, Result};
+use crate::frame::{self, Header};
+use crate::gossip::Gossip;
 use crate::model::transaction::*;
+use crate::net;
+use crate::sender;
+use crate::sequence::{Arrival, SequenceCounts, SequenceTracker};
+use crate::stats::{micros, Histogram};
+use crate::time;
 
-use std::net::UdpSocket;
-use std::time::Instant;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-pub fn start(recv_port: u16, algo: Box<dyn CompressionAlgo>) {
+/// How long a receive blocks before the receiver checks for Ctrl-C and due summaries.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Most peers whose codecs are kept. Beyond that the ones of the peer that has been quiet for the
+/// longest are dropped, so datagrams from ever new addresses can't use up the memory.
+const MAX_PEERS: usize = 1024;
+
+/// Receives and decompresses packets until Ctrl-C, then prints a summary of the whole run. With an
+/// `interval` it also prints a summary of the packets received since the last one. `quiet`
+/// suppresses the line printed per packet. Lost, duplicated and reordered packets are detected by
+/// their sequence numbers, separately for every sender. Latencies are broken down into compression,
+/// transit, decompression and deserialization. Transit times are only meaningful if sender and
+/// receiver run on the same host. With `echo` every decoded datagram is sent back to its sender.
+/// With `neighbors` the receiver relays new transactions to them like an Ict node.
+pub fn start(
+    bind_addr: &str,
+    mut registries: Registries,
+    interval: Option<Duration>,
+    quiet: bool,
+    mut echo: Option<Reencode>,
+    neighbors: Vec<(String, Reencode)>,
+) {
     // Bind socket to address
-    let recv_addr = &format!("127.0.0.1:{}", recv_port);
-    let socket = UdpSocket::bind(recv_addr).expect("Couldn't bind to receiver address");
+    let socket = UdpSocket::bind(bind_addr).expect("Couldn't bind to receiver address");
+
+    // Neighbours must be reachable from the address family we are bound to
+    let mut gossip = if neighbors.is_empty() {
+        None
+    } else {
+        let local_addr = socket.local_addr().expect("Couldn't get receiver address");
+        let neighbors = neighbors
+            .into_iter()
+            .map(|(addr, codec)| {
+                let addr = net::resolve_peer(&addr, local_addr)
+                    .expect("Couldn't resolve neighbor address");
+                (addr, codec)
+            })
+            .collect();
+
+        Some(Gossip::new(neighbors))
+    };
+    socket
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .expect("Couldn't set read timeout");
+
+    // Finish the current packet and print the summary instead of dying on Ctrl-C
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&interrupted);
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst))
+        .expect("Couldn't set Ctrl-C handler");
+
+    let mut total = Stats::new();
+    let mut recent = Stats::new();
+    let mut senders: HashMap<SocketAddr, SequenceTracker> = HashMap::new();
+    let mut counted = SequenceCounts::default();
+    let mut next_summary = interval.map(|interval| Instant::now() + interval);
 
     // Process incoming UDP packets and print events to terminal
-    let mut buf = [0; PACKET_SIZE];
-    loop {
-        // Block on receiving a new UDP packet
-        let (num_bytes, _) = socket.recv_from(&mut buf).unwrap();
+    let mut buf = [0; MAX_DATAGRAM_SIZE];
+    let mut decoded = Decoded::default();
+    let mut reply = Vec::with_capacity(MAX_DATAGRAM_SIZE);
+    while !interrupted.load(Ordering::SeqCst) {
+        if let (Some(due), Some(interval)) = (next_summary, interval) {
+            if Instant::now() >= due {
+                let counts = sequence_counts(&senders);
+                recent.print(interval, counts - counted);
+                recent = Stats::new();
+                counted = counts;
+                next_summary = Some(due + interval);
+            }
+        }
 
-        // Measure how long decompression takes
-        let start = Instant::now();
-        let decompressed = algo
-            .decompress(&buf[0..num_bytes])
-            .expect("error decompressing transaction");
-        let stop = start.elapsed();
-
-        // Print message stored in transaction
-        let tx = Transaction::from_tx_bytes(&decompressed);
-        let msg = ascii::from_tryte_string(&tx.signature_fragments);
+        // Block on receiving a new UDP packet, but not for longer than the poll interval
+        let (num_bytes, src_addr) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(ref e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                continue
+            }
+            Err(e) => panic!("Couldn't receive packet: {}", e),
+        };
+        let received_at = time::get_monotonic_time_nanos();
+
+        // A malformed packet must never take the receiver down
+        let (arrival, latency) = match receive(
+            &mut registries,
+            &mut senders,
+            src_addr,
+            &buf[0..num_bytes],
+            received_at,
+            &mut decoded,
+        ) {
+            Ok(received) => received,
+            Err(e) => {
+                total.add_dropped(num_bytes);
+                recent.add_dropped(num_bytes);
+                if !quiet {
+                    println!("Dropped {} bytes from {}: {}", num_bytes, src_addr, e);
+                }
+                continue;
+            }
+        };
+        let (header, txs) = (&decoded.header, decoded.txs());
+
+        if let Some(echo) = echo.as_mut() {
+            let echoed = echo
+                .encode(src_addr, header, txs, &mut reply)
+                .and_then(|()| Ok(socket.send_to(&reply, src_addr)?));
+            if let Err(e) = echoed {
+                println!("Couldn't echo #{} to {}: {}", header.seq, src_addr, e);
+            }
+        }
+
+        if let Some(gossip) = gossip.as_mut() {
+            gossip.relay(&socket, src_addr, header, txs);
+        }
+
+        total.add(num_bytes, txs.len(), &latency);
+        recent.add(num_bytes, txs.len(), &latency);
+        if quiet {
+            continue;
+        }
+
+        // Print message stored in the first transaction
+        let msg = ascii::from_tryte_string(&txs[0].signature_fragments);
+        let msg: String = msg.chars().take(MIN_MESSAGE_LENGTH).collect();
+        let codecs: Vec<String> = header.codec_ids.iter().map(|id| id.to_string()).collect();
         println!(
-            "Received {} bytes ({}) - Decompressed in {} ns",
+            "Received {} bytes #{} ({}{}) via {} - Latency {} ns: compress {}, transit {}, \
+             decompress {}, decode {}{}",
             num_bytes,
-            &msg[..MIN_MESSAGE_LENGTH],
-            stop.subsec_nanos()
+            header.seq,
+            msg,
+            sender::more(txs.len()),
+            codecs.join("+"),
+            latency.total().as_nanos(),
+            latency.compress.as_nanos(),
+            latency.transit.as_nanos(),
+            latency.decompress.as_nanos(),
+            latency.decode.as_nanos(),
+            match arrival {
+                Arrival::InOrder => "",
+                Arrival::OutOfOrder => " - out of order",
+                Arrival::Duplicate => " - duplicate",
+            },
+        );
+    }
+
+    total.print_total(sequence_counts(&senders));
+    if let Some(gossip) = &gossip {
+        gossip.print();
+    }
+}
+
+/// Tracks the sequence number of a datagram from `src_addr` and decodes it like `decode`. Stateful
+/// codecs must see every datagram once and in order, so duplicate and late datagrams for them
+/// are dropped before they are decompressed.
+fn receive(
+    registries: &mut Registries,
+    senders: &mut HashMap<SocketAddr, SequenceTracker>,
+    src_addr: SocketAddr,
+    datagram: &[u8],
+    received_at: u64,
+    decoded: &mut Decoded,
+) -> Result<(Arrival, Latency)> {
+    let payload = decoded.header.read_into(datagram)?;
+
+    let header = &decoded.header;
+    let arrival = senders
+        .entry(src_addr)
+        .or_default()
+        .add(header.session, header.seq);
+
+    let registry = registries.get(src_addr, &header.codec_ids);
+    if arrival != Arrival::InOrder && registry.stateful(&header.codec_ids) {
+        return Err(Error::OutOfSequence);
+    }
+
+    let latency = decode_payload(registry, payload, received_at, decoded)?;
+    Ok((arrival, latency))
+}
+
+/// Decoders for the senders. Stateful codecs like `HashRef` follow the datagrams of one sender,
+/// so each sender using them gets a registry of its own. All others share one.
+pub struct Registries {
+    create: Box<dyn Fn() -> Registry>,
+    shared: Registry,
+    per_sender: LruMap<SocketAddr, Registry>,
+}
+
+impl Registries {
+    pub fn new(create: impl Fn() -> Registry + 'static) -> Self {
+        Registries {
+            shared: create(),
+            create: Box::new(create),
+            per_sender: LruMap::new(MAX_PEERS),
+        }
+    }
+
+    /// The registry to decompress a datagram from a sender with the given codecs. A sender's own
+    /// registry is created on its first datagram with stateful codecs.
+    pub fn get(&mut self, src_addr: SocketAddr, codec_ids: &[CodecId]) -> &Registry {
+        if !self.shared.stateful(codec_ids) {
+            return &self.shared;
+        }
+
+        if !self.per_sender.contains_key(&src_addr) {
+            self.per_sender.insert(src_addr, (self.create)());
+        }
+        self.per_sender
+            .get(&src_addr)
+            .expect("registry was just looked up or inserted")
+    }
+}
+
+/// Builds an algorithm that applies the given codecs.
+pub type CreateAlgo = Box<dyn Fn(&[CodecId]) -> Result<Box<dyn CompressionAlgo>>>;
+
+/// Recompresses decoded transactions to send them on, back to their sender in echo mode or to
+/// neighbours when relaying.
+pub enum Reencode {
+    /// With the given algorithm.
+    With(Box<dyn CompressionAlgo>),
+    /// With the codecs each datagram arrived with. `create` builds them on first use and fails if
+    /// they can't be applied in that order. Stateful codecs like `HashRef` are built for every
+    /// peer, so they follow the datagrams to one peer, all others are shared.
+    Mirror {
+        create: CreateAlgo,
+        /// By their codecs and, if stateful, their peer.
+        algos: LruMap<(Option<SocketAddr>, Vec<CodecId>), Box<dyn CompressionAlgo>>,
+    },
+}
+
+impl Reencode {
+    pub fn mirror(
+        create: impl Fn(&[CodecId]) -> Result<Box<dyn CompressionAlgo>> + 'static,
+    ) -> Self {
+        Reencode::Mirror {
+            create: Box::new(create),
+            algos: LruMap::new(MAX_PEERS),
+        }
+    }
+
+    fn algo(&mut self, peer: SocketAddr, codec_ids: &[CodecId]) -> Result<&dyn CompressionAlgo> {
+        match self {
+            Reencode::With(algo) => Ok(&**algo),
+            Reencode::Mirror { create, algos } => {
+                let mut key = (None, codec_ids.to_vec());
+                if !algos.contains_key(&key) {
+                    key.0 = Some(peer);
+                    if !algos.contains_key(&key) {
+                        let algo = create(codec_ids)?;
+                        if !algo.stateful() {
+                            key.0 = None;
+                        }
+                        algos.insert(key.clone(), algo);
+                    }
+                }
+
+                let algo = algos
+                    .get(&key)
+                    .expect("algorithm was just looked up or inserted");
+                Ok(&**algo)
+            }
+        }
+    }
+
+    /// Writes a datagram for `peer` like `header` describes, batched if it is a batch and with its
+    /// session and sequence number, but with the codecs of this encoder.
+    pub fn encode(
+        &mut self,
+        peer: SocketAddr,
+        header: &Header,
+        txs: &[Transaction],
+        datagram: &mut Vec<u8>,
+    ) -> Result<()> {
+        let algo = self.algo(peer, &header.codec_ids)?;
+
+        let mut encoded = Header::new(algo.codec_ids(), header.batch);
+        encoded.session = header.session;
+        encoded.seq = header.seq;
+        datagram.clear();
+        encoded.write(datagram);
+
+        let start = Instant::now();
+        if header.batch {
+            let (mut records, mut record) = (Vec::new(), Vec::new());
+            for tx in txs {
+                record.clear();
+                algo.compress_each_into(&tx.as_bytes()[..], &mut record)?;
+                frame::write_record(&record, &mut records);
+            }
+            algo.compress_joint_into(&records, datagram)?;
+        } else {
+            algo.compress_into(&txs[0].as_bytes()[..], datagram)?;
+        }
+
+        encoded.compress_ns = start.elapsed().as_nanos().min(u128::from(u32::MAX)) as u32;
+        encoded.sent_at = time::get_monotonic_time_nanos();
+        encoded.rewrite(datagram);
+
+        Ok(())
+    }
+}
+
+fn sequence_counts(senders: &HashMap<SocketAddr, SequenceTracker>) -> SequenceCounts {
+    senders.values().map(SequenceTracker::counts).sum()
+}
+
+/// Where the time went on the way of a datagram from the sender's codec to deserialized
+/// transactions. Waiting for the next send slot and the time after receiving aren't included.
+pub struct Latency {
+    /// As measured by the sender.
+    pub compress: Duration,
+    /// From sending to receiving. Zero if the clocks disagree.
+    pub transit: Duration,
+    pub decompress: Duration,
+    /// Deserialization through `Transaction::from_tx_bytes`.
+    pub decode: Duration,
+}
+
+impl Latency {
+    pub fn total(&self) -> Duration {
+        self.compress + self.transit + self.decompress + self.decode
+    }
+}
+
+/// Running aggregates over received packets.
+struct Stats {
+    packets: u64,
+    dropped: u64,
+    transactions: u64,
+    bytes: u64,
+    compress: Histogram,
+    transit: Histogram,
+    decompress: Histogram,
+    decode: Histogram,
+    latency: Histogram,
+    /// When the first and the last packet arrived, so that idle time doesn't lower the rates.
+    first: Option<Instant>,
+    last: Option<Instant>,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Stats {
+            packets: 0,
+            dropped: 0,
+            transactions: 0,
+            bytes: 0,
+            compress: Histogram::new(),
+            transit: Histogram::new(),
+            decompress: Histogram::new(),
+            decode: Histogram::new(),
+            latency: Histogram::new(),
+            first: None,
+            last: None,
+        }
+    }
+
+    fn add(&mut self, num_bytes: usize, num_txs: usize, latency: &Latency) {
+        self.touch();
+        self.packets += 1;
+        self.transactions += num_txs as u64;
+        self.bytes += num_bytes as u64;
+        self.compress.add(latency.compress);
+        self.transit.add(latency.transit);
+        self.decompress.add(latency.decompress);
+        self.decode.add(latency.decode);
+        self.latency.add(latency.total());
+    }
+
+    fn add_dropped(&mut self, num_bytes: usize) {
+        self.touch();
+        self.packets += 1;
+        self.dropped += 1;
+        self.bytes += num_bytes as u64;
+    }
+
+    fn touch(&mut self) {
+        let now = Instant::now();
+        self.first.get_or_insert(now);
+        self.last = Some(now);
+    }
+
+    /// Prints packet and byte counts, rates, sequence counts and latency percentiles. The rates are
+    /// taken over `elapsed` and are zero if no time elapsed.
+    fn print(&self, elapsed: Duration, sequence: SequenceCounts) {
+        let per_sec = |n: u64| match elapsed.as_secs_f64() {
+            secs if secs > 0.0 => n as f64 / secs,
+            _ => 0.0,
+        };
+
+        print!(
+            "Received {} packets ({} dropped) with {} transactions and {} bytes in {:.1} s - \
+             {:.1} packets/s, {:.3} MB/s",
+            self.packets,
+            self.dropped,
+            self.transactions,
+            self.bytes,
+            elapsed.as_secs_f64(),
+            per_sec(self.packets),
+            per_sec(self.bytes) / 1e6,
+        );
+        println!(
+            " - {} lost, {} duplicates, {} out of order",
+            sequence.lost(),
+            sequence.duplicates,
+            sequence.out_of_order,
+        );
+
+        if self.latency.summary().is_none() {
+            return;
+        }
+
+        println!(
+            "    {:<10} {:>9} {:>9} {:>9} {:>9}",
+            "latency µs", "p50", "p90", "p99", "max"
         );
+        for (name, histogram) in [
+            ("compress", &self.compress),
+            ("transit", &self.transit),
+            ("decompress", &self.decompress),
+            ("decode", &self.decode),
+            ("total", &self.latency),
+        ] {
+            if let Some(summary) = histogram.summary() {
+                println!(
+                    "    {:<10} {:>9.2} {:>9.2} {:>9.2} {:>9.2}",
+                    name,
+                    micros(summary.p50),
+                    micros(summary.p90),
+                    micros(summary.p99),
+                    micros(summary.max),
+                );
+            }
+        }
+    }
+
+    /// Prints the summary over the time from the first to the last packet.
+    fn print_total(&self, sequence: SequenceCounts) {
+        let elapsed = match (self.first, self.last) {
+            (Some(first), Some(last)) => last - first,
+            _ => Duration::default(),
+        };
+        self.print(elapsed, sequence);
+    }
+}
+
+/// A decoded datagram. The receiver keeps one and `decode` overwrites it for every datagram,
+/// reusing the header, the decompressed bytes and the strings of the transactions.
+#[derive(Default)]
+pub struct Decoded {
+    pub header: Header,
+    /// The transactions of the datagram, followed by spare ones left from earlier datagrams.
+    txs: Vec<Transaction>,
+    num_txs: usize,
+    /// The decompressed transactions and where each of them ends.
+    bytes: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl Decoded {
+    pub fn txs(&self) -> &[Transaction] {
+        &self.txs[..self.num_txs]
+    }
+}
+
+/// Parses the header, decompresses the payload and deserializes the transactions into `decoded`.
+/// Returns where the time went, `received_at` is from `time::get_monotonic_time_nanos`. Once
+/// `decoded` has held datagrams as large as this one, decoding doesn't allocate.
+pub fn decode(
+    registry: &Registry,
+    datagram: &[u8],
+    received_at: u64,
+    decoded: &mut Decoded,
+) -> Result<Latency> {
+    // The header tells us which codecs the sender applied
+    let payload = decoded.header.read_into(datagram)?;
+    decode_payload(registry, payload, received_at, decoded)
+}
+
+/// Like `decode`, but for the `payload` behind a header that was already read into
+/// `decoded.header`.
+pub fn decode_payload(
+    registry: &Registry,
+    payload: &[u8],
+    received_at: u64,
+    decoded: &mut Decoded,
+) -> Result<Latency> {
+    let Decoded {
+        header,
+        txs,
+        num_txs,
+        bytes,
+        ends,
+    } = decoded;
+    *num_txs = 0;
+
+    // Measure how long decompression and deserialization take
+    let start = Instant::now();
+    bytes.clear();
+    ends.clear();
+    if header.batch {
+        registry.decompress_many_into(&header.codec_ids, payload, bytes, ends)?;
+    } else {
+        registry.decompress_into(&header.codec_ids, payload, bytes)?;
+        ends.push(bytes.len());
+    }
+    let decompress = start.elapsed();
+
+    let mut tx_start = 0;
+    for (i, &end) in ends.iter().enumerate() {
+        let tx_bytes = &bytes[tx_start..end];
+        match txs.get_mut(i) {
+            Some(tx) => tx.read_tx_bytes(tx_bytes)?,
+            None => txs.push(Transaction::from_tx_bytes(tx_bytes)?),
+        }
+        tx_start = end;
+    }
+    *num_txs = ends.len();
+
+    Ok(Latency {
+        compress: Duration::from_nanos(u64::from(header.compress_ns)),
+        transit: Duration::from_nanos(received_at.saturating_sub(header.sent_at)),
+        decompress,
+        decode: start.elapsed() - decompress,
+    })
+}
+
+/// A map that drops its least recently used entry to make room once it is full.
+pub struct LruMap<K, V> {
+    /// Every value with the access it was last used by.
+    entries: HashMap<K, (V, u64)>,
+    capacity: usize,
+    accesses: u64,
+}
+
+impl<K: Clone + Eq + Hash, V> LruMap<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        LruMap {
+            entries: HashMap::new(),
+            capacity: capacity.max(1),
+            accesses: 0,
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// The value of `key`, which is now the most recently used one.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.accesses += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        *used = self.accesses;
+        Some(value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.accesses += 1;
+        self.entries.insert(key, (value, self.accesses));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allocations;
+
+    use std::slice;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn random_transactions(seed: u64, count: usize) -> Vec<Transaction> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..count)
+            .map(|_| {
+                let (_, tx_bytes) = sender::random_transaction(&mut rng, MIN_MESSAGE_LENGTH);
+                Transaction::from_tx_bytes(&tx_bytes[..]).unwrap()
+            })
+            .collect()
+    }
+
+    fn trytes(txs: &[Transaction]) -> Vec<String> {
+        txs.iter().map(Transaction::as_tryte_string).collect()
+    }
+
+    fn new_registry() -> Registry {
+        Registry::new(Vec::new(), &TryteModel::default())
+    }
+
+    #[test]
+    fn echo_replies_decode_to_the_same_transactions() {
+        let registry = new_registry();
+        let txs = random_transactions(25, 3);
+
+        let mut echo = Reencode::mirror(|codec_ids| {
+            assert_eq!(&[CodecId::TrimAll, CodecId::Lz4][..], codec_ids);
+            Ok(Box::new(Pipeline::new(vec![
+                Box::new(TrimAll::new()),
+                Box::new(Lz4::new(0)),
+            ])))
+        });
+
+        for (batch, txs) in [(false, &txs[..1]), (true, &txs[..])] {
+            let mut header = Header::new(vec![CodecId::TrimAll, CodecId::Lz4], batch);
+            header.seq = 42;
+
+            let mut datagram = Vec::new();
+            echo.encode(([127, 0, 0, 1], 1).into(), &header, txs, &mut datagram)
+                .unwrap();
+
+            let mut echoed = Decoded::default();
+            decode(&registry, &datagram, 0, &mut echoed).unwrap();
+            assert_eq!(header.codec_ids, echoed.header.codec_ids);
+            assert_eq!(batch, echoed.header.batch);
+            assert_eq!(42, echoed.header.seq);
+            assert_eq!(trytes(txs), trytes(echoed.txs()));
+        }
+    }
+
+    #[test]
+    fn stateful_codecs_follow_each_peer() {
+        let txs = random_transactions(25, 4);
+        let peers: [SocketAddr; 2] = [([127, 0, 0, 1], 1).into(), ([127, 0, 0, 1], 2).into()];
+        let header = Header::new(vec![CodecId::HashRef], false);
+
+        // Two senders with different hashes whose references to them arrive interleaved
+        let mut other_txs = random_transactions(26, 4);
+        for tx in other_txs.iter_mut() {
+            tx.trunk = tx.address.clone();
+        }
+        let senders = [HashRef::new(), HashRef::new()];
+        let mut registries = Registries::new(new_registry);
+        let mut decoded = Decoded::default();
+        for (tx, other_tx) in txs.iter().zip(&other_txs) {
+            for ((sender, &peer), tx) in senders.iter().zip(&peers).zip(&[tx, other_tx]) {
+                let mut datagram = Vec::new();
+                header.write(&mut datagram);
+                sender
+                    .compress_into(&tx.as_bytes()[..], &mut datagram)
+                    .unwrap();
+
+                let registry = registries.get(peer, &header.codec_ids);
+                decode(registry, &datagram, 0, &mut decoded).unwrap();
+                assert_eq!(trytes(slice::from_ref(tx)), trytes(decoded.txs()));
+            }
+        }
+
+        // And two peers getting the same transactions echoed in turn
+        let mut echo = Reencode::mirror(|_| Ok(Box::new(HashRef::new())));
+        let peer_registries = [new_registry(), new_registry()];
+        for tx in &txs {
+            for (registry, &peer) in peer_registries.iter().zip(&peers) {
+                let mut datagram = Vec::new();
+                echo.encode(peer, &header, slice::from_ref(tx), &mut datagram)
+                    .unwrap();
+
+                decode(registry, &datagram, 0, &mut decoded).unwrap();
+                assert_eq!(trytes(slice::from_ref(tx)), trytes(decoded.txs()));
+            }
+        }
+    }
+
+    #[test]
+    fn only_stateful_codecs_are_kept_per_peer() {
+        let peers: [SocketAddr; 2] = [([127, 0, 0, 1], 1).into(), ([127, 0, 0, 1], 2).into()];
+
+        let mut registries = Registries::new(new_registry);
+        let stateless: Vec<*const Registry> = peers
+            .iter()
+            .map(|&peer| registries.get(peer, &[CodecId::TrimAll, CodecId::Lz4]) as *const _)
+            .collect();
+        let stateful: Vec<*const Registry> = peers
+            .iter()
+            .map(|&peer| registries.get(peer, &[CodecId::HashRef]) as *const _)
+            .collect();
+        assert_eq!(stateless[0], stateless[1]);
+        assert_ne!(stateful[0], stateful[1]);
+        assert_ne!(stateless[0], stateful[0]);
+
+        let txs = random_transactions(28, 1);
+        let mut datagram = Vec::new();
+        let mut echo = Reencode::mirror(|codec_ids| match codec_ids {
+            [CodecId::HashRef] => Ok(Box::new(HashRef::new())),
+            _ => Ok(Box::new(Lz4::new(0))),
+        });
+        for codec_ids in &[vec![CodecId::Lz4], vec![CodecId::HashRef]] {
+            let header = Header::new(codec_ids.clone(), false);
+            for &peer in &peers {
+                echo.encode(peer, &header, &txs, &mut datagram).unwrap();
+            }
+        }
+        match echo {
+            Reencode::Mirror { algos, .. } => {
+                assert!(algos.contains_key(&(None, vec![CodecId::Lz4])));
+                for &peer in &peers {
+                    assert!(algos.contains_key(&(Some(peer), vec![CodecId::HashRef])));
+                }
+                assert_eq!(3, algos.entries.len());
+            }
+            Reencode::With(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn lru_map_drops_the_least_recently_used_entry() {
+        let mut map = LruMap::new(2);
+        map.insert(1, "one");
+        map.insert(2, "two");
+
+        // Using 1 leaves 2 as the oldest
+        assert_eq!(Some(&"one"), map.get(&1));
+        map.insert(3, "three");
+        assert!(map.contains_key(&1));
+        assert!(!map.contains_key(&2));
+        assert!(map.contains_key(&3));
+
+        // Replacing a value doesn't evict anything
+        map.insert(3, "drei");
+        assert_eq!(Some(&"one"), map.get(&1));
+        assert_eq!(Some(&"drei"), map.get(&3));
+    }
+
+    #[test]
+    fn duplicates_dont_desync_stateful_codecs() {
+        let mut txs = random_transactions(27, 4);
+        for (tx, first) in txs.iter_mut().zip(&["A", "B", "C", "A"]) {
+            tx.address.replace_range(..1, first);
+        }
+        let peer = ([127, 0, 0, 1], 1).into();
+        let mut header = Header::new(vec![CodecId::HashRef], false);
+
+        // The last datagram refers to the address of the first one, which a second copy of the
+        // address in between would push further back in the cache
+        let sender = HashRef::new();
+        let datagrams: Vec<Vec<u8>> = txs
+            .iter()
+            .enumerate()
+            .map(|(seq, tx)| {
+                header.seq = seq as u32;
+                let mut datagram = Vec::new();
+                header.write(&mut datagram);
+                sender
+                    .compress_into(&tx.as_bytes()[..], &mut datagram)
+                    .unwrap();
+                datagram
+            })
+            .collect();
+
+        let mut registries = Registries::new(new_registry);
+        let mut senders = HashMap::new();
+        let mut decoded = Decoded::default();
+        let mut receive = |datagram: &[u8]| {
+            receive(
+                &mut registries,
+                &mut senders,
+                peer,
+                datagram,
+                0,
+                &mut decoded,
+            )
+            .map(|(arrival, _)| (arrival, trytes(decoded.txs())))
+        };
+
+        assert!(receive(&datagrams[0]).is_ok());
+        assert!(receive(&datagrams[1]).is_ok());
+        assert!(matches!(receive(&datagrams[1]), Err(Error::OutOfSequence)));
+        assert!(matches!(receive(&datagrams[0]), Err(Error::OutOfSequence)));
+
+        for (tx, datagram) in txs.iter().zip(&datagrams).skip(2) {
+            let (arrival, decoded) = receive(datagram).unwrap();
+            assert_eq!(Arrival::InOrder, arrival);
+            assert_eq!(trytes(slice::from_ref(tx)), decoded);
+        }
+        assert_eq!(2, senders[&peer].counts().duplicates);
+    }
+
+    #[test]
+    fn decoding_doesnt_allocate_once_warmed_up() {
+        let registry = new_registry();
+        let mut echo = Reencode::mirror(|_| {
+            Ok(Box::new(Pipeline::new(vec![
+                Box::new(TrimAll::new()),
+                Box::new(Lz4::new(0)),
+            ])))
+        });
+        let peer = ([127, 0, 0, 1], 1).into();
+
+        for &batch in &[false, true] {
+            let header = Header::new(vec![CodecId::TrimAll, CodecId::Lz4], batch);
+            let num_txs = if batch { 3 } else { 1 };
+            let datagrams: Vec<Vec<u8>> = [25, 26]
+                .iter()
+                .map(|&seed| {
+                    let txs = random_transactions(seed, num_txs);
+                    let mut datagram = Vec::new();
+                    echo.encode(peer, &header, &txs, &mut datagram).unwrap();
+                    datagram
+                })
+                .collect();
+
+            let mut decoded = Decoded::default();
+            for datagram in &datagrams {
+                decode(&registry, datagram, 0, &mut decoded).unwrap();
+            }
+            let num = allocations::count(|| {
+                decode(&registry, &datagrams[0], 0, &mut decoded).unwrap();
+            });
+            assert_eq!(0, num);
+            assert_eq!(num_txs, decoded.txs().len());
+        }
     }
 }
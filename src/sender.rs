@@ -1,69 +1,393 @@
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 
+use std::collections::VecDeque;
 use std::iter;
+use std::mem;
 use std::net::UdpSocket;
 use std::thread;
 use std::time::{Duration, Instant};
 
-use crate::algos::CompressionAlgo;
-use crate::constants::MIN_MESSAGE_LENGTH;
+use crate::algos::{CompressionAlgo, MAX_DECOMPRESSED_SIZE};
+use crate::constants::{MAX_DATAGRAM_SIZE, MIN_MESSAGE_LENGTH, PACKET_SIZE};
+use crate::convert::bytes::TxBytes;
+use crate::frame::{self, Header};
 use crate::model::transaction::*;
+use crate::net;
+use crate::time;
 
-// MODIFY THIS VALUE TO CHANGE PAUSE BETWEEN SENDS
-const SLEEP_MS: u64 = 3000;
+/// One packet every 3 seconds, slow enough to follow the output.
+pub const DEFAULT_RATE: f64 = 1.0 / 3.0;
 
-pub fn start(send_port: u16, recv_port: u16, msg_length: usize, algo: Box<dyn CompressionAlgo>) {
-    //
-    let recv_addr = &format!("127.0.0.1:{}", recv_port);
+/// How many packets the sender sends and how fast.
+pub struct Traffic {
+    /// Packets per second, `None` to send without any pause.
+    pub rate: Option<f64>,
+    /// Packets sent back to back before pausing.
+    pub burst: usize,
+    /// Stop after this many packets.
+    pub count: Option<u64>,
+    /// Stop after this long.
+    pub duration: Option<Duration>,
+}
+
+impl Traffic {
+    /// When to stop if started at `start`.
+    pub fn deadline(&self, start: Instant) -> Option<Instant> {
+        self.duration
+            .and_then(|duration| start.checked_add(duration))
+    }
+}
 
+pub fn start(
+    bind_addr: &str,
+    peer_addr: &str,
+    msg_length: usize,
+    mtu: Option<usize>,
+    traffic: Traffic,
+    quiet: bool,
+    algo: Box<dyn CompressionAlgo>,
+) {
     // Create a UDP socket
-    let send_addr = &format!("127.0.0.1:{}", send_port);
-    let socket = UdpSocket::bind(send_addr).expect("Couldn't bind to sender address");
+    let socket = UdpSocket::bind(bind_addr).expect("Couldn't bind to sender address");
+
+    // The receiver must be reachable from the address family we are bound to
+    let local_addr = socket.local_addr().expect("Couldn't get sender address");
+    let recv_addr =
+        net::resolve_peer(peer_addr, local_addr).expect("Couldn't resolve receiver address");
     let mut rng = thread_rng();
 
+    // Every datagram tells the receiver how to decompress it
+    let mut header = Header::new(algo.codec_ids(), mtu.is_some());
+
+    // Batches and the transactions that didn't fit into the previous one anymore
+    let mut batch = Batch::default();
+    let mut single_msg;
+
+    // Reused for every datagram, codecs compress right into it
+    let mut datagram = Vec::with_capacity(MAX_DATAGRAM_SIZE);
+
+    let start = Instant::now();
+    let mut pacer = Pacer::new(traffic.rate, traffic.burst);
+    let deadline = traffic.deadline(start);
+    let mut num_packets = 0;
+    let mut num_txs = 0;
+    let mut num_bytes = 0;
+
     // Send compressed UDP packets and print events to terminal
-    loop {
-        // Create a random message from alphanumberic chars
-        let msg: String = iter::repeat(())
-            .map(|()| rng.sample(Alphanumeric))
-            .take(msg_length)
-            .collect();
+    while traffic.count.is_none_or(|count| num_packets < count)
+        && traffic
+            .duration
+            .is_none_or(|duration| start.elapsed() < duration)
+    {
+        // The send and compression time are filled in right before sending
+        header.seq = num_packets as u32;
+        datagram.clear();
+        header.write(&mut datagram);
 
-        // Create a transaction from that message
-        //let tx = Transaction::default().message(&msg);
-        let tx = Transaction::from_tryte_string(&get_example_trytes()).message(&msg);
+        let (msg, txs, elapsed) = match mtu {
+            None => {
+                let (msg, tx_bytes) = random_transaction(&mut rng, msg_length);
+                single_msg = msg;
 
-        // Convert it to bytes
-        let tx_bytes = tx.as_bytes();
+                // Compress bytes behind the header
+                let start = Instant::now();
+                algo.compress_into(&tx_bytes[..], &mut datagram)
+                    .expect("error compressing transaction");
 
-        // Compress bytes
-        let start = Instant::now();
-        let compressed = algo
-            .compress(&tx_bytes[..])
-            .expect("error compressing transaction");
-        let stop = start.elapsed();
+                (single_msg.as_str(), 1, start.elapsed())
+            }
+            Some(mtu) => {
+                let elapsed = fill_batch(algo.as_ref(), &header, mtu, &mut batch, || {
+                    random_transaction(&mut rng, msg_length)
+                });
+                datagram.extend_from_slice(batch.compressed());
+
+                (batch.first_msg(), batch.num_txs(), elapsed)
+            }
+        };
 
-        // Send it to the receiver
+        // Send it to the receiver once it is due
+        if !pacer.wait(deadline) {
+            break;
+        }
+        header.compress_ns = elapsed.as_nanos().min(u128::from(u32::MAX)) as u32;
+        header.sent_at = time::get_monotonic_time_nanos();
+        header.rewrite(&mut datagram);
         socket
-            .send_to(&compressed, recv_addr)
+            .send_to(&datagram, recv_addr)
             .expect("Couldn't send packet to receiver");
 
+        num_packets += 1;
+        num_txs += txs;
+        num_bytes += datagram.len();
+        if quiet {
+            continue;
+        }
+
+        let uncompressed = txs * PACKET_SIZE;
         println!(
-            "Sent {} bytes ({}) - Compressed {} bytes in {} ns ({:.2}).",
-            compressed.len(),
+            "Sent {} bytes #{} ({}{}) - Compressed {} bytes in {} ns ({:.2}).",
+            datagram.len(),
+            header.seq,
             &msg[..MIN_MESSAGE_LENGTH],
-            tx_bytes.len(),
-            stop.subsec_nanos(),
-            tx_bytes.len() as f64 / compressed.len() as f64,
+            more(txs),
+            uncompressed,
+            elapsed.as_nanos(),
+            uncompressed as f64 / datagram.len() as f64,
         );
 
-        sleep(SLEEP_MS);
+        if let Some(report) = algo.report() {
+            println!("{}", report);
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "Sent {} packets with {} transactions and {} bytes in {:.2} s ({:.1} packets/s).",
+        num_packets,
+        num_txs,
+        num_bytes,
+        elapsed,
+        num_packets as f64 / elapsed,
+    );
+
+    // Without the lines per packet this is the only report
+    if quiet {
+        if let Some(report) = algo.report() {
+            println!("{}", report);
+        }
     }
 }
 
-fn sleep(ms: u64) {
-    thread::sleep(Duration::from_millis(ms));
+/// Spaces bursts of packets so that they go out at the given rate on average. Falls behind
+/// instead of catching up with a flood if sending is slower than the rate.
+pub struct Pacer {
+    /// Time between the starts of two bursts, `None` to never wait.
+    interval: Option<Duration>,
+    burst: usize,
+    sent_in_burst: usize,
+    next_burst: Instant,
+}
+
+impl Pacer {
+    pub fn new(rate: Option<f64>, burst: usize) -> Self {
+        let burst = burst.max(1);
+
+        Pacer {
+            interval: rate.map(|rate| Duration::from_secs_f64(burst as f64 / rate)),
+            burst,
+            sent_in_burst: 0,
+            next_burst: Instant::now(),
+        }
+    }
+
+    /// Blocks until the next packet may be sent. Returns `false` right away instead if that is
+    /// past the deadline.
+    pub fn wait(&mut self, deadline: Option<Instant>) -> bool {
+        if self.sent_in_burst == self.burst {
+            if let Some(interval) = self.interval {
+                if deadline.is_some_and(|deadline| self.next_burst + interval >= deadline) {
+                    return false;
+                }
+                self.next_burst += interval;
+
+                let now = Instant::now();
+                if self.next_burst > now {
+                    thread::sleep(self.next_burst - now);
+                } else if now - self.next_burst > interval {
+                    self.next_burst = now;
+                }
+            }
+
+            self.sent_in_burst = 0;
+        }
+
+        self.sent_in_burst += 1;
+        deadline.is_none_or(|deadline| Instant::now() < deadline)
+    }
+}
+
+/// Scratch space of `fill_batch`, kept by the sender so that every batch reuses its memory.
+#[derive(Default)]
+struct Batch {
+    /// Messages of the transactions in `records`
+    msgs: VecDeque<String>,
+    /// Records of the transactions, compressed by the per-transaction stages of a batch codec
+    records: Vec<u8>,
+    /// Length of the records up to and including each transaction
+    ends: Vec<usize>,
+    /// How long compressing each record took
+    elapsed: Vec<Duration>,
+    /// Number of transactions in the batch, the ones after it didn't fit anymore
+    len: usize,
+    /// The batch compressed jointly
+    compressed: Vec<u8>,
+    /// Joint compression of a batch that may not fit
+    trial: Vec<u8>,
+    /// Output of the per-transaction stages
+    record: Vec<u8>,
+}
+
+impl Batch {
+    fn num_txs(&self) -> usize {
+        self.len
+    }
+
+    fn first_msg(&self) -> &str {
+        &self.msgs[0]
+    }
+
+    fn compressed(&self) -> &[u8] {
+        &self.compressed
+    }
+
+    /// Drops the transactions of the batch, leaving the ones that didn't fit at the front.
+    fn remove_sent(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+
+        let offset = self.ends[self.len - 1];
+        self.msgs.drain(..self.len);
+        self.records.drain(..offset);
+        self.ends.drain(..self.len);
+        self.elapsed.drain(..self.len);
+        for end in &mut self.ends {
+            *end -= offset;
+        }
+        self.len = 0;
+    }
+}
+
+/// Adds transactions to a batch until the next one would exceed the MTU or make the batch larger
+/// than the receiver decompresses. Transactions left over from the previous batch come first.
+/// Returns how long compression took.
+///
+/// The batch is only compressed jointly once the size extrapolated from the last compression gets
+/// near the MTU. If it overflows, the largest fitting batch is searched between the last one that
+/// fit and the current one.
+fn fill_batch(
+    algo: &dyn CompressionAlgo,
+    header: &Header,
+    mtu: usize,
+    batch: &mut Batch,
+    mut next_transaction: impl FnMut() -> (String, TxBytes),
+) -> Duration {
+    let budget = mtu.saturating_sub(header.size());
+    let compress = |records: &[u8], buf: &mut Vec<u8>| {
+        buf.clear();
+        let start = Instant::now();
+        algo.compress_joint_into(records, buf)
+            .expect("error compressing batch");
+        start.elapsed()
+    };
+
+    batch.remove_sent();
+    // The largest batch known to fit: its number of transactions and how long its compression
+    // took, the compression itself is in `batch.compressed`
+    let mut fit = None;
+    let mut next_check = 0;
+
+    loop {
+        let num = batch.len;
+        if num == batch.ends.len() {
+            let (msg, tx_bytes) = next_transaction();
+
+            // Compress only the part that works on single transactions of a batch
+            batch.record.clear();
+            let start = Instant::now();
+            algo.compress_each_into(&tx_bytes[..], &mut batch.record)
+                .expect("error compressing transaction");
+            batch.elapsed.push(start.elapsed());
+
+            frame::write_record(&batch.record, &mut batch.records);
+            batch.ends.push(batch.records.len());
+            batch.msgs.push_back(msg);
+        }
+
+        // The receiver rejects batches that decompress to more than this
+        let end = batch.ends[num];
+        if num > 0 && end as u64 > MAX_DECOMPRESSED_SIZE {
+            break;
+        }
+        batch.len += 1;
+
+        if end < next_check {
+            continue;
+        }
+
+        // A single transaction is sent even if it exceeds the MTU
+        let joint_elapsed = compress(&batch.records[..end], &mut batch.trial);
+        if batch.len > 1 && batch.trial.len() > budget {
+            break;
+        }
+
+        // Check again halfway to where the MTU is reached at the current compression ratio
+        let full = budget * end / batch.trial.len().max(1);
+        next_check = end + full.saturating_sub(end) / 2;
+        mem::swap(&mut batch.compressed, &mut batch.trial);
+        fit = Some((batch.len, joint_elapsed));
+    }
+
+    let (mut num_fit, mut joint_elapsed) = fit.expect("first transaction always fits");
+
+    // The batch may have grown since the last compression without overflowing
+    if num_fit < batch.len {
+        let all_elapsed = compress(
+            &batch.records[..batch.ends[batch.len - 1]],
+            &mut batch.trial,
+        );
+        if batch.trial.len() <= budget {
+            num_fit = batch.len;
+            mem::swap(&mut batch.compressed, &mut batch.trial);
+            joint_elapsed = all_elapsed;
+        } else {
+            let mut too_many = batch.len;
+            while too_many - num_fit > 1 {
+                let mid = num_fit + (too_many - num_fit) / 2;
+                let mid_elapsed = compress(&batch.records[..batch.ends[mid - 1]], &mut batch.trial);
+                if batch.trial.len() <= budget {
+                    num_fit = mid;
+                    mem::swap(&mut batch.compressed, &mut batch.trial);
+                    joint_elapsed = mid_elapsed;
+                } else {
+                    too_many = mid;
+                }
+            }
+        }
+    }
+
+    // Stateful codecs must see every transaction exactly once, so the rest isn't compressed again
+    batch.len = num_fit;
+
+    let each_elapsed: Duration = batch.elapsed[..num_fit].iter().sum();
+    each_elapsed + joint_elapsed
+}
+
+/// Creates a transaction holding a random message from alphanumeric chars.
+pub fn random_transaction(rng: &mut impl Rng, msg_length: usize) -> (String, TxBytes) {
+    let msg: String = iter::repeat(())
+        .map(|()| rng.sample(Alphanumeric))
+        .take(msg_length)
+        .collect();
+
+    // Create a transaction from that message
+    //let tx = Transaction::default().message(&msg);
+    let tx = Transaction::from_tryte_string(&get_example_trytes())
+        .and_then(|tx| tx.message(&msg))
+        .expect("error creating transaction");
+
+    // Convert it to bytes
+    (msg, tx.as_bytes())
+}
+
+/// Mentions the rest of a batch after its first message.
+pub fn more(num_txs: usize) -> String {
+    match num_txs {
+        1 => String::new(),
+        _ => format!(" and {} more", num_txs - 1),
+    }
 }
 
 fn get_example_trytes() -> String {
@@ -80,3 +404,128 @@ fn get_example_trytes() -> String {
         sig_msg_frag, extra_data_digest, addr_value_tag_timestamps, rest
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn pacer_stops_at_the_deadline() {
+        // The second burst would be due after 2 s, long past the deadline
+        let start = Instant::now();
+        let mut pacer = Pacer::new(Some(1.0), 2);
+        let deadline = Some(start + Duration::from_secs(1));
+
+        assert!(pacer.wait(deadline));
+        assert!(pacer.wait(deadline));
+        assert!(!pacer.wait(deadline));
+
+        // Giving up doesn't wait for the deadline or the next burst
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn batches_fit_the_mtu_and_keep_the_order() {
+        use crate::algos::Lz4;
+
+        let algo = Lz4::new(0);
+        let header = Header::new(algo.codec_ids(), true);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(12);
+        let mut batch = Batch::default();
+        let mut sent = Vec::new();
+        let mut batched = Vec::new();
+
+        for _ in 0..5 {
+            fill_batch(&algo, &header, 1400, &mut batch, || {
+                let (msg, tx_bytes) = random_transaction(&mut rng, MIN_MESSAGE_LENGTH);
+                sent.push(msg.clone());
+                (msg, tx_bytes)
+            });
+
+            assert!(batch.num_txs() > 1);
+            assert!(header.size() + batch.compressed().len() <= 1400);
+            let records: Vec<u8> = algo.decompress(batch.compressed()).unwrap();
+            assert_eq!(&batch.records[..batch.ends[batch.len - 1]], &records[..]);
+
+            // One more transaction doesn't fit anymore
+            let more = &batch.records[..batch.ends[batch.len]];
+            assert!(header.size() + algo.compress(more).unwrap().len() > 1400);
+
+            batched.extend(batch.msgs.iter().take(batch.len).cloned());
+        }
+
+        // Transactions left over from one batch start the next one
+        batched.extend(batch.msgs.iter().skip(batch.len).cloned());
+        assert_eq!(sent, batched);
+    }
+
+    #[test]
+    fn batches_stay_below_the_decompression_limit() {
+        use crate::algos::Lz4;
+
+        // The same transaction over and over compresses far below any MTU
+        let algo = Lz4::new(0);
+        let header = Header::new(algo.codec_ids(), true);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(12);
+        let tx = random_transaction(&mut rng, 1458);
+        let mut batch = Batch::default();
+
+        fill_batch(&algo, &header, MAX_DATAGRAM_SIZE, &mut batch, || tx.clone());
+
+        let records = algo.decompress(batch.compressed()).unwrap();
+        assert!(records.len() as u64 <= MAX_DECOMPRESSED_SIZE);
+        assert!(records.len() as u64 + PACKET_SIZE as u64 > MAX_DECOMPRESSED_SIZE);
+        assert_eq!(1, batch.msgs.len() - batch.num_txs());
+    }
+
+    #[test]
+    fn batches_dont_allocate_once_warmed_up() {
+        use crate::algos::{Lz4Block, Pipeline, TrimAll};
+        use crate::allocations;
+
+        // Compresses each transaction and the whole batch
+        let algo = Pipeline::new(vec![
+            Box::new(TrimAll::new()),
+            Box::new(Lz4Block::new(0, 1)),
+        ]);
+        let header = Header::new(algo.codec_ids(), true);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(12);
+        let mut txs = (0..500)
+            .map(|_| random_transaction(&mut rng, MIN_MESSAGE_LENGTH))
+            .collect::<Vec<_>>()
+            .into_iter();
+        let mut batch = Batch::default();
+
+        for _ in 0..5 {
+            fill_batch(&algo, &header, 1400, &mut batch, || txs.next().unwrap());
+        }
+        let num = allocations::count(|| {
+            fill_batch(&algo, &header, 1400, &mut batch, || txs.next().unwrap());
+        });
+        assert_eq!(0, num);
+        assert!(batch.num_txs() > 1);
+    }
+
+    #[test]
+    fn pacer_keeps_the_rate() {
+        // 50 packets at 1000/s in bursts of 10 need 4 pauses of 10 ms
+        let start = Instant::now();
+        let mut pacer = Pacer::new(Some(1000.0), 10);
+        for _ in 0..50 {
+            assert!(pacer.wait(None));
+        }
+        let elapsed = start.elapsed();
+
+        assert!(elapsed >= Duration::from_millis(40));
+        assert!(elapsed < Duration::from_secs(1));
+
+        // Without a rate it never waits
+        let start = Instant::now();
+        let mut pacer = Pacer::new(None, 1);
+        for _ in 0..1000 {
+            assert!(pacer.wait(None));
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
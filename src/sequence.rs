@@ -0,0 +1,218 @@
+use std::ops::Sub;
+
+/// How many sequence numbers behind the highest one are remembered to tell duplicates from late
+/// packets.
+const WINDOW: u32 = 128;
+
+/// How a packet arrived relative to the packets before it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arrival {
+    /// Newer than all packets before. Packets skipped on the way count as lost until they arrive.
+    InOrder,
+    /// Older than the newest packet, but not seen before.
+    OutOfOrder,
+    /// Seen before.
+    Duplicate,
+}
+
+/// Packet counts derived from sequence numbers.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SequenceCounts {
+    /// Packets the sender sent from the first to the newest one received.
+    pub expected: u64,
+    /// Packets received once, no matter in which order.
+    pub received: u64,
+    pub duplicates: u64,
+    pub out_of_order: u64,
+}
+
+impl SequenceCounts {
+    /// Packets that were skipped and haven't arrived late.
+    pub fn lost(&self) -> u64 {
+        self.expected.saturating_sub(self.received)
+    }
+}
+
+impl Sub for SequenceCounts {
+    type Output = SequenceCounts;
+
+    /// The counts since an earlier snapshot.
+    fn sub(self, earlier: SequenceCounts) -> SequenceCounts {
+        SequenceCounts {
+            expected: self.expected - earlier.expected,
+            received: self.received - earlier.received,
+            duplicates: self.duplicates - earlier.duplicates,
+            out_of_order: self.out_of_order - earlier.out_of_order,
+        }
+    }
+}
+
+impl std::iter::Sum for SequenceCounts {
+    fn sum<I: Iterator<Item = SequenceCounts>>(iter: I) -> SequenceCounts {
+        iter.fold(SequenceCounts::default(), |sum, counts| SequenceCounts {
+            expected: sum.expected + counts.expected,
+            received: sum.received + counts.received,
+            duplicates: sum.duplicates + counts.duplicates,
+            out_of_order: sum.out_of_order + counts.out_of_order,
+        })
+    }
+}
+
+/// Follows the sequence numbers of one sender. Handles wrapping around after `u32::MAX`. A new
+/// session means that the sender restarted, tracking then starts over from its first packet. A
+/// packet further behind the newest one than the window counts as out of order, as there is no
+/// telling whether it is a duplicate.
+#[derive(Default)]
+pub struct SequenceTracker {
+    session: u32,
+    newest: Option<u32>,
+    /// Bit `i` is set if packet `newest - i` was received.
+    seen: u128,
+    counts: SequenceCounts,
+}
+
+impl SequenceTracker {
+    pub fn add(&mut self, session: u32, seq: u32) -> Arrival {
+        let newest = match self.newest {
+            Some(newest) if session == self.session => newest,
+            _ => return self.restart(session, seq),
+        };
+
+        let ahead = seq.wrapping_sub(newest) as i32;
+        if ahead > 0 {
+            let ahead = ahead as u32;
+            self.seen = if ahead < WINDOW {
+                (self.seen << ahead) | 1
+            } else {
+                1
+            };
+            self.newest = Some(seq);
+            self.counts.expected += u64::from(ahead);
+            self.counts.received += 1;
+            return Arrival::InOrder;
+        }
+
+        let behind = ahead.unsigned_abs();
+        if behind < WINDOW {
+            let bit = 1 << behind;
+            if self.seen & bit != 0 {
+                self.counts.duplicates += 1;
+                return Arrival::Duplicate;
+            }
+            self.seen |= bit;
+        }
+
+        self.counts.received += 1;
+        self.counts.out_of_order += 1;
+        Arrival::OutOfOrder
+    }
+
+    pub fn counts(&self) -> SequenceCounts {
+        self.counts
+    }
+
+    fn restart(&mut self, session: u32, seq: u32) -> Arrival {
+        self.session = session;
+        self.newest = Some(seq);
+        self.seen = 1;
+        self.counts.expected += 1;
+        self.counts.received += 1;
+        Arrival::InOrder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_loss_duplicates_and_reordering() {
+        let mut tracker = SequenceTracker::default();
+
+        let arrivals: Vec<Arrival> = [5, 6, 8, 9, 9, 7, 12, 6]
+            .iter()
+            .map(|&seq| tracker.add(1, seq))
+            .collect();
+
+        use Arrival::*;
+        assert_eq!(
+            vec![InOrder, InOrder, InOrder, InOrder, Duplicate, OutOfOrder, InOrder, Duplicate],
+            arrivals
+        );
+
+        // 10 and 11 are missing
+        let counts = tracker.counts();
+        assert_eq!(8, counts.expected);
+        assert_eq!(6, counts.received);
+        assert_eq!(2, counts.lost());
+        assert_eq!(2, counts.duplicates);
+        assert_eq!(1, counts.out_of_order);
+
+        // A late packet is no longer lost
+        assert_eq!(OutOfOrder, tracker.add(1, 10));
+        assert_eq!(1, tracker.counts().lost());
+    }
+
+    #[test]
+    fn handles_wrapping_and_restarts() {
+        let mut tracker = SequenceTracker::default();
+
+        assert_eq!(Arrival::InOrder, tracker.add(1, u32::MAX - 1));
+        assert_eq!(Arrival::InOrder, tracker.add(1, 1));
+        assert_eq!(Arrival::OutOfOrder, tracker.add(1, u32::MAX));
+        assert_eq!(1, tracker.counts().lost());
+
+        // A packet from before the window is late, the newest one stays the same
+        assert_eq!(Arrival::InOrder, tracker.add(1, 1000));
+        assert_eq!(Arrival::OutOfOrder, tracker.add(1, 1000 - WINDOW));
+        assert_eq!(Arrival::OutOfOrder, tracker.add(1, 0));
+        assert_eq!(Arrival::InOrder, tracker.add(1, 1001));
+        assert_eq!(Arrival::Duplicate, tracker.add(1, 1000));
+
+        let counts = tracker.counts();
+        assert_eq!(1004, counts.expected);
+        assert_eq!(7, counts.received);
+        assert_eq!(3, counts.out_of_order);
+
+        // The sender starts over at 0 in a new session
+        assert_eq!(Arrival::InOrder, tracker.add(2, 0));
+        assert_eq!(Arrival::InOrder, tracker.add(2, 1));
+        assert_eq!(Arrival::Duplicate, tracker.add(2, 0));
+    }
+
+    #[test]
+    fn new_session_restarts_tracking() {
+        let mut tracker = SequenceTracker::default();
+        for seq in 0..10 {
+            tracker.add(1, seq);
+        }
+
+        // Restarted on the same port after fewer packets than the window
+        assert_eq!(Arrival::InOrder, tracker.add(2, 0));
+        assert_eq!(Arrival::InOrder, tracker.add(2, 1));
+        assert_eq!(Arrival::Duplicate, tracker.add(2, 0));
+
+        let counts = tracker.counts();
+        assert_eq!(12, counts.expected);
+        assert_eq!(12, counts.received);
+        assert_eq!(1, counts.duplicates);
+        assert_eq!(0, counts.lost());
+    }
+
+    #[test]
+    fn counts_since_snapshot() {
+        let mut tracker = SequenceTracker::default();
+        tracker.add(1, 0);
+        tracker.add(1, 2);
+        let snapshot = tracker.counts();
+
+        tracker.add(1, 2);
+        tracker.add(1, 5);
+
+        let recent = tracker.counts() - snapshot;
+        assert_eq!(3, recent.expected);
+        assert_eq!(1, recent.received);
+        assert_eq!(1, recent.duplicates);
+        assert_eq!(2, recent.lost());
+    }
+}
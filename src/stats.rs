@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+/// Mean and percentiles of a series of measured durations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub total: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Summary {
+    /// Sorts the samples in place. Returns `None` if there are none.
+    pub fn new(samples: &mut [Duration]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        samples.sort_unstable();
+        let total: Duration = samples.iter().sum();
+
+        Some(Summary {
+            count: samples.len(),
+            total,
+            mean: total / samples.len() as u32,
+            p50: percentile(samples, 50.0),
+            p90: percentile(samples, 90.0),
+            p99: percentile(samples, 99.0),
+            max: samples[samples.len() - 1],
+        })
+    }
+}
+
+/// The smallest sample that is at least as large as `percent` percent of all samples (nearest
+/// rank). The samples must be sorted and not empty.
+pub fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Sub-buckets per power of two. Bounds the relative error of a percentile to 1/32.
+const SUB_BUCKETS: u64 = 32;
+
+/// Counts durations in buckets instead of keeping them, so that percentiles of a long run take
+/// constant memory. Durations below 64 ns are exact, larger ones are off by less than 3.2%.
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    total: Duration,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Histogram {
+            counts: vec![0; bucket(u64::MAX) + 1],
+            count: 0,
+            total: Duration::default(),
+            max: 0,
+        }
+    }
+
+    pub fn add(&mut self, duration: Duration) {
+        let nanos = duration.as_nanos().min(u128::from(u64::MAX)) as u64;
+
+        self.counts[bucket(nanos)] += 1;
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(nanos);
+    }
+
+    /// Like `Summary::new`, but percentiles are the largest duration of their bucket. Returns
+    /// `None` if nothing was added.
+    pub fn summary(&self) -> Option<Summary> {
+        if self.count == 0 {
+            return None;
+        }
+
+        Some(Summary {
+            count: self.count as usize,
+            total: self.total,
+            mean: Duration::from_nanos((self.total.as_nanos() / u128::from(self.count)) as u64),
+            p50: self.percentile(50.0),
+            p90: self.percentile(90.0),
+            p99: self.percentile(99.0),
+            max: Duration::from_nanos(self.max),
+        })
+    }
+
+    fn percentile(&self, percent: f64) -> Duration {
+        let rank = ((percent / 100.0 * self.count as f64).ceil() as u64).clamp(1, self.count);
+
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(bucket_max(index).min(self.max));
+            }
+        }
+
+        Duration::from_nanos(self.max)
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Values below `2 * SUB_BUCKETS` get a bucket each. Above, every power of two is split into
+/// `SUB_BUCKETS` buckets by the bits after the leading one.
+fn bucket(nanos: u64) -> usize {
+    if nanos < 2 * SUB_BUCKETS {
+        return nanos as usize;
+    }
+
+    let shift = 63 - nanos.leading_zeros() - SUB_BUCKETS.trailing_zeros();
+    ((u64::from(shift) + 1) * SUB_BUCKETS + (nanos >> shift) - SUB_BUCKETS) as usize
+}
+
+/// The largest value that falls into the bucket.
+fn bucket_max(index: usize) -> u64 {
+    let index = index as u64;
+    if index < 2 * SUB_BUCKETS {
+        return index;
+    }
+
+    let shift = index / SUB_BUCKETS - 1;
+    let mantissa = SUB_BUCKETS + index % SUB_BUCKETS;
+    ((mantissa + 1) << shift).wrapping_sub(1)
+}
+
+/// Duration in microseconds with fractions, for printing.
+pub fn micros(duration: Duration) -> f64 {
+    duration.as_nanos() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_works() {
+        let mut samples: Vec<Duration> = (1..=100).rev().map(Duration::from_micros).collect();
+        let summary = Summary::new(&mut samples).unwrap();
+
+        assert_eq!(100, summary.count);
+        assert_eq!(Duration::from_micros(5050), summary.total);
+        assert_eq!(Duration::from_nanos(50_500), summary.mean);
+        assert_eq!(Duration::from_micros(50), summary.p50);
+        assert_eq!(Duration::from_micros(90), summary.p90);
+        assert_eq!(Duration::from_micros(99), summary.p99);
+        assert_eq!(Duration::from_micros(100), summary.max);
+
+        assert_eq!(None, Summary::new(&mut []));
+    }
+
+    #[test]
+    fn histogram_works() {
+        let mut histogram = Histogram::new();
+        assert_eq!(None, histogram.summary());
+
+        let mut samples: Vec<Duration> = (1..=1000).map(|i| Duration::from_nanos(i * i)).collect();
+        for &sample in &samples {
+            histogram.add(sample);
+        }
+
+        let exact = Summary::new(&mut samples).unwrap();
+        let approx = histogram.summary().unwrap();
+
+        assert_eq!(exact.count, approx.count);
+        assert_eq!(exact.total, approx.total);
+        assert_eq!(exact.mean, approx.mean);
+        assert_eq!(exact.max, approx.max);
+        for (exact, approx) in [
+            (exact.p50, approx.p50),
+            (exact.p90, approx.p90),
+            (exact.p99, approx.p99),
+        ] {
+            assert!(approx >= exact);
+            assert!(approx.as_nanos() as f64 <= exact.as_nanos() as f64 * 1.032);
+        }
+    }
+
+    #[test]
+    fn histogram_buckets_are_contiguous() {
+        assert_eq!(0, bucket(0));
+        assert_eq!(63, bucket(63));
+        assert_eq!(64, bucket(64));
+        assert_eq!(64, bucket(65));
+
+        for index in 0..bucket(u64::MAX) {
+            assert_eq!(index, bucket(bucket_max(index)));
+            assert_eq!(index + 1, bucket(bucket_max(index) + 1));
+        }
+        assert_eq!(u64::MAX, bucket_max(bucket(u64::MAX)));
+    }
+
+    #[test]
+    fn percentile_of_few_samples() {
+        let samples = [Duration::from_nanos(3), Duration::from_nanos(7)];
+
+        assert_eq!(Duration::from_nanos(3), percentile(&samples, 0.0));
+        assert_eq!(Duration::from_nanos(3), percentile(&samples, 50.0));
+        assert_eq!(Duration::from_nanos(7), percentile(&samples, 50.1));
+        assert_eq!(Duration::from_nanos(7), percentile(&samples, 100.0));
+    }
+}
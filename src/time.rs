@@ -1,4 +1,8 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use lazy_static::lazy_static;
+
+use std::io;
+use std::sync::Once;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 #[macro_export]
 macro_rules! sleep {
@@ -14,3 +18,38 @@ pub fn get_unix_time_millis() -> i64 {
         .expect("error determining system time");
     (unix_time.as_secs() * 1000 + u64::from(unix_time.subsec_millis())) as i64
 }
+
+lazy_static! {
+    /// Where the fallback for the monotonic clock counts from.
+    static ref ANCHOR: Instant = Instant::now();
+}
+
+static FALLBACK_WARNING: Once = Once::new();
+
+/// Nanoseconds on the monotonic clock of the host (`CLOCK_MONOTONIC`). It never goes backwards,
+/// isn't affected by adjustments of the system clock and is shared by all processes on the host,
+/// so timestamps of a sender and a receiver on the same host are comparable. It counts from an
+/// arbitrary point, e.g. the boot.
+///
+/// If the clock can't be read, the time since the first such failure in this process is used
+/// instead. It is still monotonic, but no longer comparable to the timestamps of other processes.
+pub fn get_monotonic_time_nanos() -> u64 {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `now` is a valid place for the result, nothing else is touched.
+    let result = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    if result != 0 {
+        let error = io::Error::last_os_error();
+        FALLBACK_WARNING.call_once(|| {
+            eprintln!(
+                "warning: couldn't read the monotonic clock, transit times are meaningless: {}",
+                error
+            )
+        });
+        return ANCHOR.elapsed().as_nanos() as u64;
+    }
+
+    now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::Path;
+
+use crate::algos::{compress_batch, decompress_batch, CompressionAlgo, Huffman, TryteModel, Zstd};
+use crate::corpus;
+
+/// Trains a Zstd dictionary and optionally a tryte model on the corpus. Returns `false` if the
+/// corpus is too small to train on and to hold back the requested part for testing.
+pub fn start(
+    corpus_path: &Path,
+    dict_path: &Path,
+    max_dict_size: usize,
+    test_percent: usize,
+    level: i32,
+    model_path: Option<&Path>,
+) -> bool {
+    // Convert the corpus into transaction bytes
+    let samples: Vec<Vec<u8>> = corpus::read(corpus_path)
+        .iter()
+        .map(|tx| tx.as_bytes().to_vec())
+        .collect();
+
+    // Hold back the last part of the corpus to measure the achieved ratio
+    let num_test = samples.len() * test_percent / 100;
+    let (train_samples, test_samples) = samples.split_at(samples.len() - num_test);
+
+    if train_samples.is_empty() || (test_percent > 0 && test_samples.is_empty()) {
+        eprintln!(
+            "error: {} transactions are too few to train on and hold back {}% for testing",
+            samples.len(),
+            test_percent
+        );
+        return false;
+    }
+
+    let dictionary = match zstd::dict::from_samples(train_samples, max_dict_size) {
+        Ok(dictionary) => dictionary,
+        Err(e) => {
+            eprintln!(
+                "error: couldn't train dictionary from {} transactions: {}",
+                train_samples.len(),
+                e
+            );
+            return false;
+        }
+    };
+
+    fs::write(dict_path, &dictionary).expect("Couldn't write dictionary file");
+
+    println!(
+        "Trained dictionary from {} samples ({} held out) - wrote {} bytes to {}.",
+        train_samples.len(),
+        test_samples.len(),
+        dictionary.len(),
+        dict_path.display(),
+    );
+
+    if !test_samples.is_empty() {
+        let with_dict = Zstd::with_dictionary(level, dictionary);
+        let without_dict = Zstd::new(level);
+
+        println!(
+            "Held-out ratio: {:.2} with dictionary, {:.2} without.",
+            ratio(&with_dict, test_samples),
+            ratio(&without_dict, test_samples),
+        );
+    }
+
+    if let Some(model_path) = model_path {
+        train_model(model_path, train_samples, test_samples);
+    }
+
+    true
+}
+
+fn train_model(model_path: &Path, train_samples: &[Vec<u8>], test_samples: &[Vec<u8>]) {
+    let mut model = TryteModel::empty();
+    for sample in train_samples {
+        model.add(sample);
+    }
+
+    model
+        .save(model_path)
+        .expect("Couldn't write tryte model file");
+
+    println!("Wrote tryte model to {}.", model_path.display());
+
+    if !test_samples.is_empty() {
+        println!(
+            "Held-out ratio: {:.2} with Huffman model, {:.2} with embedded model.",
+            ratio(&Huffman::new(&model), test_samples),
+            ratio(&Huffman::new(&TryteModel::default()), test_samples),
+        );
+    }
+}
+
+/// Also checks that every sample survives the round trip.
+fn ratio(algo: &dyn CompressionAlgo, samples: &[Vec<u8>]) -> f64 {
+    let compressed = compress_batch(algo, samples).expect("error compressing transaction");
+    let decompressed =
+        decompress_batch(algo, &compressed).expect("error decompressing transaction");
+    assert!(
+        decompressed == samples,
+        "held-out samples don't survive the round trip"
+    );
+
+    let uncompressed: usize = samples.iter().map(|sample| sample.len()).sum();
+    let compressed: usize = compressed.iter().map(|bytes| bytes.len()).sum();
+
+    uncompressed as f64 / compressed as f64
+}
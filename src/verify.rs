@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use crate::algos::{CompressionAlgo, Registry, TryteModel};
+use crate::corpus;
+use crate::error::Result;
+use crate::model::transaction::*;
+
+/// Failed round trips reported per algorithm. The rest are only counted.
+const MAX_REPORTED_FAILURES: usize = 10;
+
+/// Compresses and decompresses every transaction of the corpus with each algorithm and checks
+/// that the rebuilt transaction equals the original. Decompression goes through a `Registry` like
+/// in the receiver. Returns whether all round trips succeeded.
+pub fn start(
+    corpus_path: &Path,
+    algos: Vec<(String, Box<dyn CompressionAlgo>)>,
+    dictionary: Vec<u8>,
+    model: &TryteModel,
+) -> bool {
+    let txs = corpus::read(corpus_path);
+
+    println!(
+        "Verifying {} algorithms on {} transactions.",
+        algos.len(),
+        txs.len()
+    );
+
+    let mut all_passed = true;
+
+    for (name, algo) in algos {
+        // A fresh registry, so stateful codecs start out in lockstep
+        let registry = Registry::new(dictionary.clone(), model);
+        let mut num_failed = 0;
+
+        for (i, tx) in txs.iter().enumerate() {
+            let failure = match round_trip(algo.as_ref(), &registry, tx) {
+                Ok(tx2) if tx2.as_tryte_string() == tx.as_tryte_string() => continue,
+                Ok(tx2) => match tx.first_difference(&tx2) {
+                    Some((field, offset)) => format!("{} differs at tryte {}", field, offset),
+                    None => "tryte strings differ".to_string(),
+                },
+                Err(e) => e.to_string(),
+            };
+
+            num_failed += 1;
+            if num_failed <= MAX_REPORTED_FAILURES {
+                println!("{}: transaction {}: {}", name, i + 1, failure);
+            }
+        }
+
+        if num_failed == 0 {
+            println!("{}: all {} round trips passed", name, txs.len());
+        } else {
+            println!(
+                "{}: {} of {} round trips failed",
+                name,
+                num_failed,
+                txs.len()
+            );
+            all_passed = false;
+        }
+    }
+
+    all_passed
+}
+
+fn round_trip(
+    algo: &dyn CompressionAlgo,
+    registry: &Registry,
+    tx: &Transaction,
+) -> Result<Transaction> {
+    let compressed = algo.compress(&tx.as_bytes())?;
+    let decompressed = registry.decompress(&algo.codec_ids(), &compressed)?;
+
+    Transaction::from_tx_bytes(&decompressed)
+}